@@ -1,4 +1,5 @@
 import 'dart:async';
+import 'dart:typed_data';
 
 import 'package:logging/logging.dart';
 import 'package:test/test.dart';
@@ -14,6 +15,20 @@ void main() {
             [Contact(id: 1, fullName: "Alice Smith", status: Status.pending)]));
   });
 
+  test('a stream completes once the Rust stream ends', () async {
+    final accounts = AccountsApi();
+    expect(
+        await accounts.contacts().toList(),
+        equals(
+            [Contact(id: 1, fullName: "Alice Smith", status: Status.pending)]));
+
+    var count = 0;
+    await for (final _ in accounts.optionalContacts()) {
+      count++;
+    }
+    expect(count, equals(2));
+  });
+
   test('can get a contact from Rust by String arg', () async {
     final accounts = AccountsApi();
     expect(
@@ -22,6 +37,37 @@ void main() {
             Contact(id: 1, fullName: "Alice Smith", status: Status.pending)));
   });
 
+  test('can receive an optional value from a function and a stream',
+      () async {
+    final accounts = AccountsApi();
+    expect(
+        await accounts.findContact(userId: "1"),
+        equals(
+            Contact(id: 1, fullName: "Alice Smith", status: Status.pending)));
+    expect(await accounts.findContact(userId: "2"), isNull);
+    expect(
+        await accounts.optionalContacts().take(2).toList(),
+        equals([
+          Contact(id: 1, fullName: "Alice Smith", status: Status.pending),
+          null
+        ]));
+  });
+
+  test('can get a contact from Rust synchronously', () {
+    final accounts = AccountsApi();
+    expect(
+        accounts.contactSync(userId: "1"),
+        equals(
+            Contact(id: 1, fullName: "Alice Smith", status: Status.pending)));
+    expect(accounts.syncScalarI64(val: 21), equals(42));
+  });
+
+  test('a synchronous function throws an ApiError when an error is returned',
+      () {
+    final accounts = AccountsApi();
+    expect(() => accounts.syncError(), throwsA(isA<AccountsApiError>()));
+  });
+
   test(
       'can call a function with optional args with none of the args or all of the args',
       () async {
@@ -70,6 +116,300 @@ void main() {
     expect(() async => await accounts.scalarEmpty(), returnsNormally);
   });
 
+  test('can call functions and streams that do not return a Result',
+      () async {
+    final accounts = AccountsApi();
+    expect(await accounts.infallibleI64(val: 10), equals(11));
+    expect(await accounts.infallibleStream().take(2).toList(),
+        equals(["one", "two"]));
+    expect(accounts.infallibleSync(val: "hello"), equals("HELLO"));
+  });
+
+  test('a stream error either continues or terminates the stream', () async {
+    final accounts = AccountsApi();
+    final continued = [];
+    await accounts
+        .continueOnError()
+        .handleError((error) => continued.add(error.runtimeType))
+        .forEach(continued.add);
+    expect(continued, equals([1, AccountsApiError, 3]));
+
+    final terminated = [];
+    await accounts
+        .terminateOnError()
+        .handleError((error) => terminated.add(error.runtimeType))
+        .forEach(terminated.add);
+    expect(terminated, equals([1, AccountsApiError]));
+  });
+
+  test('can pass integer and float args narrower than 64 bits', () async {
+    final accounts = AccountsApi();
+    expect(
+        await accounts.narrowScalars(
+            signed8: -128,
+            signed16: 32767,
+            signed32: -2147483648,
+            unsigned8: 255,
+            unsigned16: 65535,
+            unsigned32: 4294967295,
+            unsigned64: Uint64.parse('18446744073709551615'),
+            signed128: Int128.parse('-170141183460469231731687303715884105728'),
+            unsigned128:
+                Uint128.parse('340282366920938463463374607431768211455'),
+            float32: 1.5,
+            optionalUnsigned64: Uint64.parse('10')),
+        equals(
+            '-128 32767 -2147483648 255 65535 4294967295 18446744073709551615 '
+            '-170141183460469231731687303715884105728 '
+            '340282366920938463463374607431768211455 1.5 None Some(10)'));
+  });
+
+  test('throws an ArgumentError when an arg is out of range for its Rust type',
+      () async {
+    final accounts = AccountsApi();
+    expect(
+        () async => await accounts.narrowScalars(
+            signed8: 128,
+            signed16: 0,
+            signed32: 0,
+            unsigned8: 0,
+            unsigned16: 0,
+            unsigned32: 0,
+            unsigned64: Uint64.parse('0'),
+            signed128: Int128.parse('0'),
+            unsigned128: Uint128.parse('0'),
+            float32: 0),
+        throwsArgumentError);
+    expect(
+        () async => await accounts.narrowScalars(
+            signed8: 0,
+            signed16: 0,
+            signed32: 0,
+            unsigned8: 0,
+            unsigned16: 0,
+            unsigned32: 0,
+            unsigned64: Uint64.parse('0'),
+            signed128: Int128.parse('0'),
+            unsigned128: Uint128.parse('0'),
+            float32: 0,
+            optionalUnsigned16: -1),
+        throwsArgumentError);
+  });
+
+  test('can pass and return byte buffers', () async {
+    final accounts = AccountsApi();
+    final bytes = Uint8List.fromList([1, 2, 3, 255]);
+    expect(await accounts.reverseBytes(data: bytes),
+        equals(Uint8List.fromList([255, 3, 2, 1])));
+    expect(await accounts.reverseBytes(data: Uint8List(0)), equals([]));
+    expect(await accounts.bytesError(data: bytes), equals(bytes));
+    expect(await accounts.byteChunks(data: bytes).toList(),
+        equals([Uint8List.fromList([1, 2]), Uint8List.fromList([3, 255])]));
+    expect(accounts.syncBytes(data: bytes),
+        equals(Uint8List.fromList([2, 3, 4, 0])));
+  });
+
+  test('an arg that Rust rejects is thrown as a MembraneArgumentError',
+      () async {
+    final accounts = AccountsApi();
+    final bytes = Uint8List(64);
+    final rejected = isA<MembraneArgumentError>()
+        .having((e) => e.name, 'name', 'data')
+        .having((e) => e.message, 'message', contains('maximum'));
+    accounts.maxArgumentLength(bytes: 16);
+    try {
+      await expectLater(accounts.reverseBytes(data: bytes), throwsA(rejected));
+      expect(() => accounts.syncBytes(data: bytes), throwsA(rejected));
+      await expectLater(accounts.byteChunks(data: bytes),
+          emitsInOrder([emitsError(rejected), emitsDone]));
+    } finally {
+      accounts.maxArgumentLength(bytes: 256 * 1024 * 1024);
+    }
+  });
+
+  test('a Rust panic is thrown as a MembranePanicError', () async {
+    final accounts = AccountsApi();
+    expect(
+        () async => await accounts.panicError(val: 1),
+        throwsA(isA<MembranePanicError>()
+            .having((e) => e.message, 'message', 'a panic with 1')));
+    expect(
+        () => accounts.syncPanic(),
+        throwsA(isA<MembranePanicError>()
+            .having((e) => e.message, 'message', 'a synchronous panic')));
+  });
+
+  test('can pass a Dart stream to a Rust function', () async {
+    final accounts = AccountsApi();
+    expect(await accounts.sumStream(values: Stream.fromIterable([1, 2, 3])),
+        equals(6));
+    expect(await accounts.sumStream(values: Stream.empty()), equals(0));
+
+    final words = StreamController<String>();
+    final uppercased =
+        accounts.uppercaseStream(prefix: '> ', words: words.stream);
+    words.add('one');
+    words.add('two');
+    words.close();
+    expect(await uppercased.take(2).toList(), equals(['> ONE', '> TWO']));
+  });
+
+  test('can send values from Dart into a channel', () async {
+    final accounts = AccountsApi();
+    final contact =
+        Contact(id: 5, fullName: "Eve Smith", status: Status.active);
+    final events = accounts.contactEvents().first;
+    accounts.sendContactEvents(value: contact);
+    expect(await events, equals(contact));
+  });
+
+  test('every listener of a broadcast channel receives each value', () async {
+    final accounts = AccountsApi();
+    accounts.sendStatusUpdates(value: Status.active);
+    final first = accounts.statusUpdates().take(2).toList();
+    final second = accounts.statusUpdates().take(2).toList();
+    // give both subscriptions time to reach Rust, the earlier value is replayed
+    await Future.delayed(const Duration(milliseconds: 50));
+    accounts.sendStatusUpdates(value: Status.pending);
+    expect(await first, equals([Status.active, Status.pending]));
+    expect(await second, equals([Status.active, Status.pending]));
+  });
+
+  test('a broadcast stops forwarding once nobody listens', () async {
+    final accounts = AccountsApi();
+    // the tasks of earlier listeners are dropped by Rust after they were canceled
+    while (activeTaskCount(namespace: 'accounts') > 0) {
+      await Future.delayed(const Duration(milliseconds: 10));
+    }
+    final subscription = accounts.statusUpdates().listen((_) {});
+    await Future.delayed(const Duration(milliseconds: 50));
+    // the subscription and the task forwarding the channel to it
+    expect(activeTaskCount(namespace: 'accounts'), equals(2));
+    await subscription.cancel();
+    while (activeTaskCount(namespace: 'accounts') > 0) {
+      await Future.delayed(const Duration(milliseconds: 10));
+    }
+  });
+
+  test('cancelling every task stops a broadcast until it is listened to again',
+      () async {
+    final accounts = AccountsApi();
+    final done = Completer<void>();
+    accounts.statusUpdates().listen((_) {}, onDone: done.complete);
+    accounts.sendStatusUpdates(value: Status.active);
+    await Future.delayed(const Duration(milliseconds: 50));
+    // the subscription and the task forwarding the channel to it
+    expect(activeTaskCount(namespace: 'accounts'), greaterThanOrEqualTo(2));
+    expect(cancelAllTasks(namespace: 'accounts'), greaterThanOrEqualTo(2));
+    expect(activeTaskCount(namespace: 'accounts'), equals(0));
+    await done.future;
+
+    final updates = accounts.statusUpdates().take(2).toList();
+    await Future.delayed(const Duration(milliseconds: 50));
+    accounts.sendStatusUpdates(value: Status.pending);
+    expect(await updates, equals([Status.active, Status.pending]));
+  });
+
+  test('can report progress while a function runs', () async {
+    final accounts = AccountsApi();
+    final call = accounts.importContacts(count: 4);
+    final progress = call.progress.toList();
+    expect((await call.result).map((contact) => contact.id),
+        equals([0, 1, 2, 3]));
+    expect(await progress, equals([0.25, 0.5, 0.75, 1.0]));
+  });
+
+  test('pausing a stream stops Rust from producing more items', () async {
+    final accounts = AccountsApi();
+    final first = Completer<void>();
+    late StreamSubscription<int> subscription;
+    subscription = accounts.counterStream().listen((_) {
+      if (!first.isCompleted) {
+        subscription.pause();
+        first.complete();
+      }
+    });
+    await first.future;
+    await Future.delayed(const Duration(milliseconds: 50));
+    final produced = accounts.counterProduced();
+    await Future.delayed(const Duration(milliseconds: 50));
+    expect(accounts.counterProduced(), equals(produced));
+    await subscription.cancel();
+  });
+
+  test('a stream only produces the items that Dart granted credits for',
+      () async {
+    final accounts = AccountsApi();
+    final produced = accounts.counterProduced();
+    final subscription = accounts.counterStream().listen(null)..pause();
+    await Future.delayed(const Duration(milliseconds: 50));
+    expect(accounts.counterProduced() - produced, lessThanOrEqualTo(16));
+    await subscription.cancel();
+  });
+
+  test('a canceled function can clean up before it is dropped', () async {
+    final accounts = AccountsApi();
+    final cleanups = accounts.cancelCleanups();
+    expect(await accounts.cancelableStream().take(2).toList(), equals([0, 1]));
+    await Future.delayed(const Duration(milliseconds: 100));
+    expect(accounts.cancelCleanups(), equals(cleanups + 1));
+  });
+
+  test('can cancel every running task at once', () async {
+    final accounts = AccountsApi();
+    final running = activeTaskCount();
+    final done = Completer<void>();
+    accounts.cancelableStream().listen((_) {}, onDone: done.complete);
+    final slow = expectLater(accounts.slowFunction(sleepFor: 50),
+        throwsA(isA<MembraneCancelledError>()));
+    await Future.delayed(const Duration(milliseconds: 20));
+    expect(activeTaskCount(), equals(running + 2));
+    expect(activeTaskCount(namespace: 'accounts'), greaterThanOrEqualTo(2));
+    expect(cancelAllTasks(namespace: 'locations'), equals(0));
+    expect(cancelAllTasks(), equals(running + 2));
+    // the stream ends once Rust notices the cancellation and the future throws
+    await done.future;
+    await slow;
+    // the stream's task is counted until it returns within its grace period
+    while (activeTaskCount() > 0) {
+      await Future.delayed(const Duration(milliseconds: 10));
+    }
+  });
+
+  test('can call a function spawned on a custom runtime', () async {
+    final accounts = AccountsApi();
+    expect(await accounts.threadPoolRuntime(val: 2), equals(6));
+  });
+
+  test('a function or stream that exceeds its timeout throws a TimeoutException',
+      () async {
+    final accounts = AccountsApi();
+    expect(await accounts.slowFunction(sleepFor: 10), equals(10));
+    expect(() async => await accounts.slowFunction(sleepFor: 1000),
+        throwsA(isA<TimeoutException>()));
+    expect(await accounts.slowStream(sleepFor: 10).take(2).toList(),
+        equals([0, 1]));
+    expect(() async => await accounts.slowStream(sleepFor: 30).toList(),
+        throwsA(isA<TimeoutException>()));
+
+    // the stream is still closed after the timeout
+    final events = [];
+    await accounts
+        .slowStream(sleepFor: 30)
+        .handleError(events.add)
+        .forEach(events.add);
+    expect(events.last, isA<TimeoutException>());
+  });
+
+  test('a function returning bytes throws an ApiError when an error is returned',
+      () async {
+    final accounts = AccountsApi();
+    expect(() async => await accounts.bytesError(),
+        throwsA(isA<AccountsApiError>()));
+    expect(() async => await accounts.bytesError(data: Uint8List(0)),
+        throwsA(isA<AccountsApiError>()));
+  });
+
   test(
       'test that a function throws an ApiError instance when an error is returned',
       () async {
@@ -130,6 +470,48 @@ void main() {
         ], count: 1, total: 1)));
   });
 
+  test('can pass an arg that serializes to more than 255 bytes', () async {
+    final accounts = AccountsApi();
+    final ids = List.generate(1000, (i) => i);
+    final contacts = await accounts.contactsById(ids: ids);
+    expect(contacts.count, equals(1000));
+    expect(contacts.data.map((contact) => contact.id), equals(ids));
+  });
+
+  test('can pass a vector and an optional vector as function args', () async {
+    final accounts = AccountsApi();
+    expect(
+        (await accounts.contactsById(ids: [1, 2])),
+        equals(Contacts(data: [
+          Contact(id: 1, fullName: 'Alice Smith', status: Status.pending),
+          Contact(id: 2, fullName: 'Alice Smith', status: Status.pending)
+        ], count: 2, total: 2)));
+    expect(
+        (await accounts.contactsById(ids: [3], statuses: [Status.active]))
+            .data
+            .first
+            .status,
+        equals(Status.active));
+  });
+
+  test('can receive a vector, a map, and a tuple as the returned value',
+      () async {
+    final accounts = AccountsApi();
+    expect(
+        await accounts.contactsList(),
+        equals([
+          Contact(id: 1, fullName: 'Alice Smith', status: Status.pending),
+          Contact(id: 2, fullName: 'Alice Smith', status: Status.active)
+        ]));
+    expect(await accounts.contactStatuses(),
+        equals({'1': Status.pending, '2': Status.active}));
+    expect(
+        await accounts.contactWithCount(userId: '1'),
+        equals(Tuple2(
+            Contact(id: 1, fullName: 'Alice Smith', status: Status.pending),
+            1)));
+  });
+
   test('can pass an enum as a function arg', () async {
     final accounts = AccountsApi();
     expect((await accounts.enumArg(status: Status.active)).status,
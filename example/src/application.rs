@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicI64, Ordering};
 
+use async_channel::{Receiver, Sender};
 use data::OptionsDemo;
-use membrane::async_dart;
+use membrane::{async_dart, sync_dart, CancellationToken, Progress};
+use once_cell::sync::Lazy;
 use tokio_stream::Stream;
 
 use crate::data::{self, MoreTypes};
@@ -11,6 +15,21 @@ pub fn contacts() -> impl Stream<Item = Result<data::Contact, data::Error>> {
   futures::stream::iter(vec![Ok(data::Contact::default())])
 }
 
+#[async_dart(namespace = "accounts", sender = true)]
+pub static CONTACT_EVENTS: Lazy<(
+  Sender<Result<data::Contact, String>>,
+  Receiver<Result<data::Contact, String>>,
+)> = Lazy::new(async_channel::unbounded);
+
+#[async_dart(namespace = "accounts", sender = true, broadcast = true, replay = true)]
+pub static STATUS_UPDATES: Lazy<(Sender<data::Status>, Receiver<data::Status>)> =
+  Lazy::new(async_channel::unbounded);
+
+#[async_dart(namespace = "accounts")]
+pub fn optional_contacts() -> impl Stream<Item = Result<Option<data::Contact>, data::Error>> {
+  futures::stream::iter(vec![Ok(Some(data::Contact::default())), Ok(None)])
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn contact(user_id: String) -> Result<data::Contact, data::Error> {
   Ok(data::Contact {
@@ -19,6 +38,22 @@ pub async fn contact(user_id: String) -> Result<data::Contact, data::Error> {
   })
 }
 
+#[async_dart(namespace = "accounts")]
+pub async fn find_contact(user_id: String) -> Result<Option<data::Contact>, data::Error> {
+  match user_id.as_str() {
+    "1" => Ok(Some(data::Contact::default())),
+    _ => Ok(None),
+  }
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn contact_sync(user_id: String) -> Result<data::Contact, data::Error> {
+  Ok(data::Contact {
+    id: user_id.parse().unwrap(),
+    ..data::Contact::default()
+  })
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn update_contact(
   id: String,
@@ -65,12 +100,132 @@ pub async fn scalar_empty() -> Result<(), String> {
   Ok(())
 }
 
+#[async_dart(namespace = "accounts")]
+pub async fn sum_stream(values: impl Stream<Item = i64>) -> i64 {
+  use futures::StreamExt;
+  values
+    .fold(0, |sum, value| async move { sum + value })
+    .await
+}
+
+#[async_dart(namespace = "accounts")]
+pub fn uppercase_stream(
+  prefix: String,
+  words: impl Stream<Item = String>,
+) -> impl Stream<Item = String> {
+  use futures::StreamExt;
+  words.map(move |word| format!("{}{}", prefix, word.to_uppercase()))
+}
+
+#[async_dart(namespace = "accounts", timeout_ms = 100)]
+pub async fn slow_function(sleep_for: i64) -> Result<i64, String> {
+  tokio::time::sleep(std::time::Duration::from_millis(sleep_for as u64)).await;
+  Ok(sleep_for)
+}
+
+#[async_dart(namespace = "accounts", timeout_ms = 100)]
+pub fn slow_stream(sleep_for: i64) -> impl Stream<Item = i64> {
+  futures::stream::unfold(0, move |count| async move {
+    tokio::time::sleep(std::time::Duration::from_millis(sleep_for as u64)).await;
+    Some((count, count + 1))
+  })
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn import_contacts(
+  count: i64,
+  progress: Progress<f64>,
+) -> Result<Vec<data::Contact>, String> {
+  let mut contacts = vec![];
+  for id in 0..count {
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    contacts.push(data::Contact {
+      id,
+      ..Default::default()
+    });
+    progress.send((id + 1) as f64 / count as f64);
+  }
+  Ok(contacts)
+}
+
+static COUNTER_PRODUCED: AtomicI64 = AtomicI64::new(0);
+
+#[async_dart(namespace = "accounts")]
+pub fn counter_stream() -> impl Stream<Item = i64> {
+  use futures::StreamExt;
+  futures::stream::iter(0..).inspect(|_| {
+    COUNTER_PRODUCED.fetch_add(1, Ordering::SeqCst);
+  })
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn counter_produced() -> i64 {
+  COUNTER_PRODUCED.load(Ordering::SeqCst)
+}
+
+static CANCEL_CLEANUPS: AtomicI64 = AtomicI64::new(0);
+
+#[async_dart(namespace = "accounts", cancel_grace_ms = 500)]
+pub fn cancelable_stream(token: CancellationToken) -> impl Stream<Item = i64> {
+  futures::stream::unfold((0, token), |(count, token)| async move {
+    if token.is_cancelled() {
+      CANCEL_CLEANUPS.fetch_add(1, Ordering::SeqCst);
+      return None;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    Some((count, (count + 1, token)))
+  })
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn cancel_cleanups() -> i64 {
+  CANCEL_CLEANUPS.load(Ordering::SeqCst)
+}
+
+#[async_dart(namespace = "accounts", runtime = "crate::THREAD_POOL")]
+pub async fn thread_pool_runtime(val: i64) -> i64 {
+  val * 3
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn scalar_i32(val: i64) -> Result<i32, String> {
   assert!(val == 123);
   Ok(val.try_into().unwrap())
 }
 
+#[async_dart(namespace = "accounts")]
+#[allow(clippy::too_many_arguments)]
+pub async fn narrow_scalars(
+  signed_8: i8,
+  signed_16: i16,
+  signed_32: i32,
+  unsigned_8: u8,
+  unsigned_16: u16,
+  unsigned_32: u32,
+  unsigned_64: u64,
+  signed_128: i128,
+  unsigned_128: u128,
+  float_32: f32,
+  optional_unsigned_16: Option<u16>,
+  optional_unsigned_64: Option<u64>,
+) -> Result<String, String> {
+  Ok(format!(
+    "{} {} {} {} {} {} {} {} {} {} {:?} {:?}",
+    signed_8,
+    signed_16,
+    signed_32,
+    unsigned_8,
+    unsigned_16,
+    unsigned_32,
+    unsigned_64,
+    signed_128,
+    unsigned_128,
+    float_32,
+    optional_unsigned_16,
+    optional_unsigned_64
+  ))
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn scalar_i64(val: i64) -> Result<i64, String> {
   assert!(val == 10);
@@ -101,11 +256,90 @@ pub async fn scalar_bool(val: bool) -> Result<bool, String> {
   Ok(val)
 }
 
+#[async_dart(namespace = "accounts")]
+pub async fn infallible_i64(val: i64) -> i64 {
+  val + 1
+}
+
+#[async_dart(namespace = "accounts")]
+pub fn infallible_stream() -> impl Stream<Item = String> {
+  futures::stream::iter(vec!["one".to_string(), "two".to_string()])
+}
+
+#[async_dart(namespace = "accounts", on_error = "continue")]
+pub fn continue_on_error() -> impl Stream<Item = Result<i64, String>> {
+  futures::stream::iter(vec![Ok(1), Err("two".to_string()), Ok(3)])
+}
+
+#[async_dart(namespace = "accounts", on_error = "terminate")]
+pub fn terminate_on_error() -> impl Stream<Item = Result<i64, String>> {
+  futures::stream::iter(vec![Ok(1), Err("two".to_string()), Ok(3)])
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn infallible_sync(val: String) -> String {
+  val.to_uppercase()
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn reverse_bytes(data: Vec<u8>) -> Result<Vec<u8>, String> {
+  Ok(data.into_iter().rev().collect())
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn bytes_error(data: Option<bytes::Bytes>) -> Result<bytes::Bytes, String> {
+  match data {
+    Some(data) if !data.is_empty() => Ok(data),
+    _ => Err("no bytes were received".to_string()),
+  }
+}
+
+#[async_dart(namespace = "accounts")]
+pub fn byte_chunks(data: bytes::Bytes) -> impl Stream<Item = bytes::Bytes> {
+  futures::stream::iter(
+    data
+      .chunks(2)
+      .map(bytes::Bytes::copy_from_slice)
+      .collect::<Vec<bytes::Bytes>>(),
+  )
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn sync_bytes(data: Vec<u8>) -> Vec<u8> {
+  data.into_iter().map(|byte| byte.wrapping_add(1)).collect()
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn max_argument_length(bytes: u64) -> u64 {
+  membrane::set_max_argument_length(bytes);
+  bytes
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn panic_error(val: i64) -> Result<i64, String> {
+  panic!("a panic with {}", val)
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn sync_panic() -> Result<i64, String> {
+  panic!("a synchronous panic")
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn scalar_error() -> Result<bool, String> {
   Err("an error message".to_string())
 }
 
+#[sync_dart(namespace = "accounts")]
+pub fn sync_scalar_i64(val: i64) -> Result<i64, String> {
+  Ok(val * 2)
+}
+
+#[sync_dart(namespace = "accounts")]
+pub fn sync_error() -> Result<(), String> {
+  Err("a sync error message".to_string())
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn more_types(types: data::MoreTypes) -> Result<data::MoreTypes, String> {
   let return_value = MoreTypes {
@@ -144,6 +378,59 @@ pub async fn filter_arg(filter: data::Filter) -> Result<data::Contacts, String>
   })
 }
 
+#[async_dart(namespace = "accounts")]
+pub async fn contacts_by_id(
+  ids: Vec<i64>,
+  statuses: Option<Vec<data::Status>>,
+) -> Result<data::Contacts, String> {
+  let mut statuses = statuses.unwrap_or_default().into_iter();
+  let data: Vec<data::Contact> = ids
+    .into_iter()
+    .map(|id| data::Contact {
+      id,
+      status: statuses.next().unwrap_or_default(),
+      ..data::Contact::default()
+    })
+    .collect();
+
+  Ok(data::Contacts {
+    count: data.len() as i32,
+    total: data.len() as i32,
+    data,
+  })
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn contacts_list() -> Result<Vec<data::Contact>, String> {
+  Ok(vec![
+    data::Contact::default(),
+    data::Contact {
+      id: 2,
+      status: data::Status::Active,
+      ..data::Contact::default()
+    },
+  ])
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn contact_statuses() -> Result<HashMap<String, data::Status>, String> {
+  let mut statuses = HashMap::new();
+  statuses.insert("1".to_string(), data::Status::Pending);
+  statuses.insert("2".to_string(), data::Status::Active);
+  Ok(statuses)
+}
+
+#[async_dart(namespace = "accounts")]
+pub async fn contact_with_count(user_id: String) -> Result<(data::Contact, i64), String> {
+  Ok((
+    data::Contact {
+      id: user_id.parse().unwrap(),
+      ..data::Contact::default()
+    },
+    1,
+  ))
+}
+
 #[async_dart(namespace = "accounts")]
 pub async fn enum_arg(status: data::Status) -> Result<data::Contact, String> {
   Ok(data::Contact {
@@ -10,9 +10,28 @@ pub(crate) static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
   Builder::new_multi_thread()
     .worker_threads(2)
     .thread_name("example")
+    .enable_time()
     .build()
     .unwrap()
 });
 
+// any executor can be used by implementing `membrane::Runtime`, see `application::thread_pool_runtime`
+pub(crate) struct ThreadPool(futures::executor::ThreadPool);
+
+impl membrane::Runtime for ThreadPool {
+  fn spawn<T>(&self, future: T)
+  where
+    T: std::future::Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+    self.0.spawn_ok(async {
+      future.await;
+    });
+  }
+}
+
+pub(crate) static THREAD_POOL: Lazy<ThreadPool> =
+  Lazy::new(|| ThreadPool(futures::executor::ThreadPool::new().unwrap()));
+
 // this is necessary for bin.rs to be able to inspect lib.rs
 pub fn load() {}
@@ -8,7 +8,8 @@
 //! Functions published to Dart use Rust types for their parameters including scalar types, structs, or enums -
 //! no need to work with C types in your Rust code.
 //! Both async functions and streams are supported, return values are handled via a zero-copy buffer
-//! using the bincode encoding (bincode is very efficient because it encodes data only as opposed to structure + data).
+//! using the bincode encoding (bincode is very efficient because it encodes data only as opposed to structure + data)
+//! or optionally BCS, see `Membrane::with_encoding`.
 //! Membrane is being used by a large project with stringent performance
 //! requirements and with this zero-copy encoding approach we were able to achieve zero
 //! frame drops in Flutter while transferring significant amounts of data from Rust.
@@ -53,7 +54,9 @@
 //! By default Membrane stores metadata during the compile step whenever the project is
 //! compiled in debug mode. This has two implications:
 //! 1. `cargo run --bin generator --release` won't work.
-//! 1. A library compiled in `release` mode will have no Membrane metadata in the resulting binary.
+//! 1. A library compiled in `release` mode will have no Membrane function metadata in the resulting
+//!    binary. It does keep the type traces behind `membrane_schema_hash`, so serde_reflection
+//!    tracing is compiled into release binaries as well.
 //!
 //! If you need to force a different behavior the feature flags `skip-generate` and `generate` are
 //! available to override the default behavior.
@@ -68,14 +71,17 @@ pub use ffi_helpers;
 #[doc(hidden)]
 pub use futures;
 #[doc(hidden)]
+pub use futures_timer;
+#[doc(hidden)]
 pub use inventory;
 #[doc(hidden)]
-pub use membrane_macro::{async_dart, dart_enum};
+pub use membrane_macro::{async_dart, dart_enum, sync_dart};
 #[doc(hidden)]
 pub use serde_reflection;
 
 use heck::CamelCase;
-use membrane_types::dart::dart_fn_return_type;
+use membrane_types::dart::{dart_deserializer, dart_fn_return_type, MAX_TUPLE_SIZE};
+use membrane_types::is_byte_buffer;
 use serde_reflection::{ContainerFormat, Error, Registry, Samples, Tracer, TracerConfig};
 use std::{
   collections::HashMap,
@@ -90,14 +96,237 @@ pub struct Function {
   pub extern_c_fn_types: String,
   pub fn_name: String,
   pub is_stream: bool,
+  pub is_sync: bool,
   pub return_type: String,
-  pub error_type: String,
+  pub error_type: Option<String>,
   pub namespace: String,
   pub disable_logging: bool,
+  pub timeout: Option<u64>,
+  pub broadcast: bool,
+  pub replay: bool,
+  pub progress_type: Option<String>,
   pub output: String,
   pub dart_outer_params: String,
   pub dart_transforms: String,
   pub dart_inner_args: String,
+  pub dart_input_stream: Option<String>,
+  pub encoding: Encoding,
+}
+
+///
+/// An executor for the tasks spawned by `#[async_dart]` functions.
+///
+/// Anything with a compatible `spawn` method (such as a `tokio::runtime::Runtime`) can be used as
+/// the runtime directly. Implement this trait to plug in any other executor:
+///
+/// ```ignore
+/// struct AsyncStd;
+///
+/// impl membrane::Runtime for AsyncStd {
+///   fn spawn<T>(&self, future: T)
+///   where
+///     T: std::future::Future + Send + 'static,
+///     T::Output: Send + 'static,
+///   {
+///     async_std::task::spawn(future);
+///   }
+/// }
+///
+/// static RUNTIME: AsyncStd = AsyncStd;
+/// ```
+pub trait Runtime {
+  fn spawn<T>(&self, future: T)
+  where
+    T: std::future::Future + Send + 'static,
+    T::Output: Send + 'static;
+}
+
+///
+/// Sets the runtime that a crate's `#[async_dart]` functions are spawned on when they don't name
+/// one with `runtime`. They use `crate::RUNTIME`, so this is invoked at the crate root in place of
+/// declaring a `RUNTIME` static there:
+///
+/// ```ignore
+/// membrane::runtime!(crate::executors::BACKGROUND);
+/// ```
+#[macro_export]
+macro_rules! runtime {
+  ($runtime:path) => {
+    #[doc(hidden)]
+    #[allow(unused_imports)]
+    pub(crate) use $runtime as RUNTIME;
+  };
+}
+
+///
+/// Lets an `#[async_dart]` function wind down gracefully when Dart cancels it.
+///
+/// Declare it as a parameter and Membrane will pass one in, it's not part of the generated Dart
+/// signature. Once Dart cancels the call the token is triggered and the function has the grace
+/// period (`cancel_grace_ms`, one second by default) to return before it's dropped:
+///
+/// ```ignore
+/// #[async_dart(namespace = "accounts")]
+/// pub async fn import(token: membrane::CancellationToken) -> Result<i64, String> {
+///   let transaction = begin().await;
+///   for row in rows() {
+///     if token.is_cancelled() {
+///       transaction.rollback().await;
+///       return Err("canceled".to_string());
+///     }
+///     transaction.insert(row).await;
+///   }
+///   transaction.commit().await
+/// }
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(std::sync::Arc<CancellationState>);
+
+#[derive(Debug, Default)]
+struct CancellationState {
+  cancelled: std::sync::atomic::AtomicBool,
+  /// The waker of each pending `Cancelled` future, keyed by the future so that it's only kept once
+  wakers: std::sync::Mutex<std::collections::BTreeMap<u64, std::task::Waker>>,
+  next_waiter: std::sync::atomic::AtomicU64,
+}
+
+impl CancellationToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn cancel(&self) {
+    self
+      .0
+      .cancelled
+      .store(true, std::sync::atomic::Ordering::SeqCst);
+    let wakers = std::mem::take(&mut *self.wakers());
+    wakers.into_values().for_each(std::task::Waker::wake);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.cancelled.load(std::sync::atomic::Ordering::SeqCst)
+  }
+
+  /// Resolves once the token has been cancelled
+  pub fn cancelled(&self) -> Cancelled<'_> {
+    Cancelled {
+      token: self,
+      waiter: self
+        .0
+        .next_waiter
+        .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+    }
+  }
+
+  fn wakers(&self) -> std::sync::MutexGuard<'_, std::collections::BTreeMap<u64, std::task::Waker>> {
+    self
+      .0
+      .wakers
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+}
+
+/// The future returned by [`CancellationToken::cancelled`]
+#[derive(Debug)]
+pub struct Cancelled<'a> {
+  token: &'a CancellationToken,
+  waiter: u64,
+}
+
+impl std::future::Future for Cancelled<'_> {
+  type Output = ();
+
+  fn poll(
+    self: std::pin::Pin<&mut Self>,
+    cx: &mut std::task::Context<'_>,
+  ) -> std::task::Poll<Self::Output> {
+    if self.token.is_cancelled() {
+      return std::task::Poll::Ready(());
+    }
+
+    let mut wakers = self.token.wakers();
+    // check again while holding the lock in case `cancel` ran in between
+    if self.token.is_cancelled() {
+      return std::task::Poll::Ready(());
+    }
+    match wakers.get_mut(&self.waiter) {
+      Some(waker) if waker.will_wake(cx.waker()) => {}
+      Some(waker) => *waker = cx.waker().clone(),
+      None => {
+        wakers.insert(self.waiter, cx.waker().clone());
+      }
+    }
+
+    std::task::Poll::Pending
+  }
+}
+
+impl Drop for Cancelled<'_> {
+  fn drop(&mut self) {
+    self.token.wakers().remove(&self.waiter);
+  }
+}
+
+///
+/// Reports progress to Dart while an `#[async_dart]` function is running.
+///
+/// Declare it as a parameter and Membrane will pass one in, the generated Dart method then returns
+/// a `WithProgress<T, P>` with both the `Future<T>` result and a `Stream<P>` of the progress updates:
+///
+/// ```ignore
+/// #[async_dart(namespace = "accounts")]
+/// pub async fn import(files: Vec<String>, progress: membrane::Progress<i64>) -> Result<i64, String> {
+///   for (count, file) in files.iter().enumerate() {
+///     import_file(file).await?;
+///     progress.send(count as i64 + 1);
+///   }
+///   Ok(files.len() as i64)
+/// }
+/// ```
+///
+pub struct Progress<P> {
+  isolate: allo_isolate::Isolate,
+  wrapped: bool,
+  encoding: Encoding,
+  _progress: std::marker::PhantomData<fn(P)>,
+}
+
+impl<P: serde::Serialize> Progress<P> {
+  #[doc(hidden)]
+  pub fn new(isolate: allo_isolate::Isolate, wrapped: bool, encoding: Encoding) -> Self {
+    Self {
+      isolate,
+      wrapped,
+      encoding,
+      _progress: std::marker::PhantomData,
+    }
+  }
+
+  pub fn send(&self, progress: P) {
+    let buffer = encoding::frame(self.encoding, &(frame::PROGRESS, progress));
+    // functions returning a byte buffer need every other frame wrapped in a list
+    if self.wrapped {
+      self
+        .isolate
+        .post(vec![allo_isolate::ZeroCopyBuffer(buffer)]);
+    } else {
+      self.isolate.post(allo_isolate::ZeroCopyBuffer(buffer));
+    }
+  }
+}
+
+impl<P> Clone for Progress<P> {
+  fn clone(&self) -> Self {
+    Self {
+      isolate: self.isolate,
+      wrapped: self.wrapped,
+      encoding: self.encoding,
+      _progress: std::marker::PhantomData,
+    }
+  }
 }
 
 #[doc(hidden)]
@@ -113,8 +342,21 @@ pub struct DeferredEnumTrace {
   pub trace: fn(tracer: &mut serde_reflection::Tracer),
 }
 
+/// The types and signature of a function, or the variants of an enum, that go into the schema hash of
+/// its namespace. Unlike the traces above they're kept in release builds so the library can be
+/// checked against its Dart package.
+#[doc(hidden)]
+pub struct DeferredSchema {
+  pub namespace: &'static str,
+  /// A hash of the function's signature and encoding computed by the macro, unused for enums
+  pub fingerprint: u64,
+  pub is_enum: bool,
+  pub trace: fn(tracer: &mut serde_reflection::Tracer, samples: &mut serde_reflection::Samples),
+}
+
 inventory::collect!(DeferredTrace);
 inventory::collect!(DeferredEnumTrace);
+inventory::collect!(DeferredSchema);
 
 pub struct Membrane {
   package_name: String,
@@ -126,8 +368,10 @@ pub struct Membrane {
   namespaced_fn_registry: HashMap<String, Vec<Function>>,
   generated: bool,
   c_style_enums: bool,
+  encoding: Option<Encoding>,
 }
 
+#[allow(clippy::needless_lifetimes)]
 impl<'a> Membrane {
   #[allow(clippy::new_without_default)]
   pub fn new() -> Self {
@@ -197,6 +441,16 @@ impl<'a> Membrane {
       namespaces,
       generated: false,
       c_style_enums: true,
+      encoding: std::env::var("MEMBRANE_ENCODING")
+        .ok()
+        .map(|encoding| match encoding.as_str() {
+          "bincode" => Encoding::Bincode,
+          "bcs" => Encoding::Bcs,
+          _ => panic!(
+            "MEMBRANE_ENCODING must be `bincode` or `bcs`, found `{}`",
+            encoding
+          ),
+        }),
     }
   }
 
@@ -210,7 +464,7 @@ impl<'a> Membrane {
       !path.as_ref().to_str().unwrap().is_empty(),
       "package_destination_dir() cannot be called with an empty path"
     );
-    if self.destination == PathBuf::from("membrane_output") {
+    if self.destination == Path::new("membrane_output") {
       self.destination = path.as_ref().to_path_buf();
     }
     self
@@ -267,6 +521,22 @@ impl<'a> Membrane {
     ))]
     return self;
 
+    // the Rust side can only decode the encoding that it was compiled for
+    if let Some(function) = self
+      .namespaced_fn_registry
+      .values()
+      .flatten()
+      .find(|function| function.encoding != self.encoding())
+    {
+      panic!(
+        "`{}` in the `{}` namespace was compiled for {:?} but the package is generated for {:?}, set `encoding` on the macro to match",
+        function.fn_name,
+        function.namespace,
+        function.encoding,
+        self.encoding()
+      );
+    }
+
     // remove all previously generated type and header files
     let _ = std::fs::remove_dir_all(self.destination.join("lib"));
     let _ = std::fs::remove_file(self.destination.join("pubspec.yaml"));
@@ -274,11 +544,14 @@ impl<'a> Membrane {
 
     let installer = serde_generate::dart::Installer::new(self.destination.to_path_buf());
     installer.install_serde_runtime().unwrap();
-    installer.install_bincode_runtime().unwrap();
+    match self.encoding() {
+      Encoding::Bincode => installer.install_bincode_runtime().unwrap(),
+      Encoding::Bcs => installer.install_bcs_runtime().unwrap(),
+    }
 
     for namespace in self.namespaces.iter() {
       let config = serde_generate::CodeGeneratorConfig::new(namespace.to_string())
-        .with_encodings(vec![serde_generate::Encoding::Bincode])
+        .with_encodings(vec![self.encoding().into()])
         .with_c_style_enums(self.c_style_enums);
 
       let registry = match self.namespaced_enum_registry.get(namespace).unwrap() {
@@ -327,6 +600,22 @@ impl<'a> Membrane {
     self
   }
 
+  ///
+  /// The encoding of every value passed between Dart and Rust, `Encoding::Bincode` by default.
+  /// The functions have to be compiled for the same encoding, see the `encoding` option of the macros.
+  ///
+  /// Can be overridden with the environment variable `MEMBRANE_ENCODING` (`bincode` or `bcs`).
+  pub fn with_encoding(&mut self, encoding: Encoding) -> &mut Self {
+    if self.encoding.is_none() {
+      self.encoding = Some(encoding);
+    }
+    self
+  }
+
+  fn encoding(&self) -> Encoding {
+    self.encoding.unwrap_or(Encoding::Bincode)
+  }
+
   ///
   /// Write a header file for each namespace that provides the C types
   /// needed by ffigen to generate the FFI bindings.
@@ -349,6 +638,9 @@ impl<'a> Membrane {
 
     if self.generated {
       self.create_loader();
+      self.create_tuples();
+      self.create_errors();
+      self.create_progress();
       self.format_package();
     }
 
@@ -387,6 +679,7 @@ impl<'a> Membrane {
   /// Private implementations
   ///
 
+  #[allow(clippy::empty_line_after_doc_comments)]
   fn write_pubspec(&mut self) -> &mut Self {
     // serde-generate uses the last namespace as the pubspec name and dart doesn't
     // like that so we set a proper package name from the basename or from an explicitly given name
@@ -510,7 +803,18 @@ headers:
  */
 #include <stdint.h>
 
-int32_t membrane_cancel_membrane_task(const int32_t *task_handle);
+typedef uint64_t membrane_task_t;
+
+int32_t membrane_cancel_membrane_task(membrane_task_t task);
+int32_t membrane_pause_membrane_task(membrane_task_t task);
+int32_t membrane_resume_membrane_task(membrane_task_t task);
+int32_t membrane_grant_credits(membrane_task_t task, uint64_t credits);
+int32_t membrane_cancel_all_tasks(const char *ns);
+int32_t membrane_active_task_count(const char *ns);
+int32_t membrane_free_membrane_buffer(const uint8_t *buffer);
+int32_t membrane_last_error_length(void);
+int32_t membrane_last_error_message(char *buffer, int32_t length);
+uint64_t membrane_schema_hash(const char *ns);
 "#;
 
     let mut buffer =
@@ -546,6 +850,7 @@ int32_t membrane_cancel_membrane_task(const int32_t *task_handle);
 // Generated by `membrane`
 import 'dart:ffi';
 import 'dart:io' show Platform;
+import 'package:ffi/ffi.dart';
 import 'package:logging/logging.dart';
 
 import './ffi_bindings.dart' as ffi_bindings;
@@ -574,6 +879,9 @@ DynamicLibrary _open() {{
   throw UnsupportedError('This platform is not supported.');
 }}
 
+/// The schema hash of each namespace when this package was generated
+const _schemaHashes = {{{schema_hashes}}};
+
 typedef _StoreDartPostCobjectC = Void Function(
   Pointer<NativeFunction<Int8 Function(Int64, Pointer<Dart_CObject>)>> ptr,
 );
@@ -593,12 +901,69 @@ _load() {{
   Logger('membrane').fine('Initializing Dart_PostCObject');
   storeDartPostCobject(NativeApi.postCObject);
 
+  // a library built from different Rust types would silently mis-decode every call
+  Logger('membrane').fine('Comparing schema hashes');
+  _schemaHashes.forEach((namespace, expected) {{
+    final actual =
+        _withNamespace(namespace, (ns) => bindings.membrane_schema_hash(ns));
+    // zero when the library doesn't know the namespace at all
+    if (actual != {unchecked} && actual != expected) {{
+      throw StateError(
+          'Stale bindings: the `$namespace` namespace of {lib} does not match the Dart package, regenerate the package or rebuild the library');
+    }}
+  }});
+
   return bindings;
 }}
 
 final bindings = _load();
+
+/// Cancels the running Rust tasks of every namespace, or only of [namespace], and returns how many
+/// there were. Tasks started before a hot restart keep running so this is best called at startup.
+int cancelAllTasks({{String? namespace}}) =>
+    _withNamespace(namespace, (ns) => bindings.membrane_cancel_all_tasks(ns));
+
+/// The number of Rust tasks that haven't completed or been cancelled, optionally only in [namespace]
+int activeTaskCount({{String? namespace}}) =>
+    _withNamespace(namespace, (ns) => bindings.membrane_active_task_count(ns));
+
+/// The message of the last error raised by a Rust call on this thread, which is then cleared
+String? lastErrorMessage() {{
+  final length = bindings.membrane_last_error_length();
+  if (length <= 0) {{
+    return null;
+  }}
+  final buffer = calloc<Int8>(length);
+  try {{
+    if (bindings.membrane_last_error_message(buffer, length) <= 0) {{
+      return null;
+    }}
+    return buffer.cast<Utf8>().toDartString();
+  }} finally {{
+    calloc.free(buffer);
+  }}
+}}
+
+int _withNamespace(String? namespace, int Function(Pointer<Int8>) call) {{
+  if (namespace == null) {{
+    return call(nullptr);
+  }}
+  final ns = namespace.toNativeUtf8().cast<Int8>();
+  try {{
+    return call(ns);
+  }} finally {{
+    calloc.free(ns);
+  }}
+}}
 "#,
       lib = self.library,
+      unchecked = UNCHECKED_SCHEMA as i64,
+      schema_hashes = self
+        .namespaces
+        .iter()
+        .map(|namespace| format!("'{}': {}", namespace, schema_hash(namespace) as i64))
+        .collect::<Vec<String>>()
+        .join(", "),
     );
 
     let path = self.destination.join("lib").join("src").join("loader.dart");
@@ -607,6 +972,125 @@ final bindings = _load();
     self
   }
 
+  fn create_tuples(&mut self) -> &mut Self {
+    let mut tuples = String::from(
+      r#"// AUTO GENERATED FILE, DO NOT EDIT
+//
+// Generated by `membrane`
+import 'package:meta/meta.dart';
+"#,
+    );
+
+    for size in 1..=MAX_TUPLE_SIZE {
+      let items = (1..=size)
+        .map(|i| format!("item{}", i))
+        .collect::<Vec<String>>();
+      tuples += &format!(
+        r#"
+@immutable
+class Tuple{size}<{generics}> {{
+  const Tuple{size}({params});
+
+  {fields}
+
+  @override
+  bool operator ==(Object other) {{
+    if (identical(this, other)) return true;
+    return other is Tuple{size} && {equality};
+  }}
+
+  @override
+  int get hashCode => Object.hashAll([{items}]);
+
+  @override
+  String toString() => 'Tuple{size}(' + [{items}].join(', ') + ')';
+}}
+"#,
+        size = size,
+        generics = (1..=size)
+          .map(|i| format!("T{}", i))
+          .collect::<Vec<String>>()
+          .join(", "),
+        params = items
+          .iter()
+          .map(|item| format!("this.{}", item))
+          .collect::<Vec<String>>()
+          .join(", "),
+        fields = (1..=size)
+          .map(|i| format!("final T{i} item{i};", i = i))
+          .collect::<Vec<String>>()
+          .join("\n  "),
+        equality = items
+          .iter()
+          .map(|item| format!("{item} == other.{item}", item = item))
+          .collect::<Vec<String>>()
+          .join(" && "),
+        items = items.join(", "),
+      );
+    }
+
+    let path = self.destination.join("lib").join("src").join("tuples.dart");
+    std::fs::write(path, tuples).unwrap();
+
+    self
+  }
+
+  fn create_errors(&mut self) -> &mut Self {
+    let errors = r#"// AUTO GENERATED FILE, DO NOT EDIT
+//
+// Generated by `membrane`
+
+/// Thrown when Rust couldn't decode an argument, such as one over the maximum argument length
+class MembraneArgumentError extends ArgumentError {
+  MembraneArgumentError(String name, String message) : super(message, name);
+}
+
+/// Thrown when the Rust function panicked instead of returning a value
+class MembranePanicError extends Error {
+  final String message;
+  MembranePanicError(this.message);
+
+  @override
+  String toString() => 'Rust panicked: $message';
+}
+
+/// Thrown when the Rust task was cancelled, such as by `cancelAllTasks`, before it returned
+class MembraneCancelledError extends Error {
+  @override
+  String toString() => 'The Rust task was cancelled';
+}
+"#;
+
+    let path = self.destination.join("lib").join("src").join("errors.dart");
+    std::fs::write(path, errors).unwrap();
+
+    self
+  }
+
+  fn create_progress(&mut self) -> &mut Self {
+    let progress = r#"// AUTO GENERATED FILE, DO NOT EDIT
+//
+// Generated by `membrane`
+
+/// Returned by Rust functions that report their progress while running
+class WithProgress<T, P> {
+  final Future<T> result;
+  final Stream<P> progress;
+  const WithProgress(this.result, this.progress);
+}
+"#;
+
+    let path = self
+      .destination
+      .join("lib")
+      .join("src")
+      .join("progress.dart");
+    std::fs::write(path, progress).unwrap();
+
+    self
+  }
+
+  #[allow(clippy::unnecessary_get_then_check)]
   fn create_class(&mut self, namespace: String) -> &mut Self {
     use std::io::prelude::*;
     let path = self
@@ -631,6 +1115,7 @@ final bindings = _load();
       r#"// AUTO GENERATED FILE, DO NOT EDIT
 //
 // Generated by `membrane`
+import 'dart:async' show StreamController, TimeoutException;
 import 'dart:ffi';
 import 'dart:isolate' show ReceivePort;
 import 'dart:typed_data';
@@ -639,11 +1124,21 @@ import 'package:logging/logging.dart';
 import 'package:meta/meta.dart';
 
 import './src/loader.dart' as loader;
-import './src/bincode/bincode.dart';
+import './src/{encoding}/{encoding}.dart';
+import './src/errors.dart';
+import './src/progress.dart';
+import './src/tuples.dart';
 import './src/{ns}/{ns}.dart';
 
+export './src/errors.dart';
+export './src/loader.dart' show activeTaskCount, cancelAllTasks;
+export './src/progress.dart';
+export './src/tuples.dart';
 export './src/{ns}/{ns}.dart' hide TraitHelpers;
 
+typedef _Serializer = {encoding_class}Serializer;
+typedef _Deserializer = {encoding_class}Deserializer;
+
 final _bindings = loader.bindings;
 final _loggingDisabled = bool.fromEnvironment('MEMBRANE_DISABLE_LOGS');
 
@@ -659,7 +1154,9 @@ class {class_name}Api {{
   const {class_name}Api();
 "#,
       ns = &namespace,
-      class_name = &namespace.to_camel_case()
+      class_name = &namespace.to_camel_case(),
+      encoding = self.encoding().runtime(),
+      encoding_class = self.encoding().runtime().to_camel_case()
     );
 
     let mut buffer = std::fs::File::create(path).expect("class could not be written at path");
@@ -693,24 +1190,82 @@ impl Function {
   }
 
   pub fn signature(&mut self) -> &mut Self {
+    if self.broadcast {
+      self.output += "  /// Every listener receives each value sent to the channel";
+      self.output += if self.replay {
+        ", starting with the most recent one.\n"
+      } else {
+        ".\n"
+      };
+    }
+
+    let fn_params = if self.dart_outer_params.is_empty() {
+      String::new()
+    } else {
+      format!("{{{}}}", self.dart_outer_params)
+    };
+
+    if self.is_sync {
+      self.output += format!(
+        "  {return_type} {fn_name}({fn_params})",
+        return_type = dart_fn_return_type(&self.return_type),
+        fn_name = self.fn_name,
+        fn_params = fn_params,
+      )
+      .as_str();
+      return self;
+    }
+
+    // the call itself runs in an async closure so that progress can be returned right away
+    if let Some(progress_type) = &self.progress_type {
+      self.output += format!(
+        "  WithProgress<{return_type}, {progress_type}> {fn_name}({fn_params}) {{
+    final _progress = StreamController<{progress_type}>();
+    final _result = () async",
+        return_type = dart_fn_return_type(&self.return_type),
+        progress_type = dart_fn_return_type(progress_type),
+        fn_name = self.fn_name,
+        fn_params = fn_params,
+      )
+      .as_str();
+      return self;
+    }
+
     self.output += format!(
       "  {output_style}<{return_type}> {fn_name}({fn_params}){asink}",
       output_style = if self.is_stream { "Stream" } else { "Future" },
       return_type = dart_fn_return_type(&self.return_type),
       fn_name = self.fn_name,
-      fn_params = if self.dart_outer_params.is_empty() {
-        String::new()
-      } else {
-        format!("{{{}}}", self.dart_outer_params)
-      },
+      fn_params = fn_params,
       asink = if self.is_stream { " async*" } else { " async" }
     )
     .as_str();
     self
   }
+
+  fn push_fn_name(&self) -> String {
+    self
+      .extern_c_fn_name
+      .replacen("membrane_", "membrane_push_", 1)
+  }
+
   pub fn c_signature(&mut self) -> &mut Self {
+    if self.is_sync {
+      self.output += format!(
+        "uint8_t *{extern_c_fn_name}({extern_c_fn_types});",
+        extern_c_fn_name = self.extern_c_fn_name,
+        extern_c_fn_types = if self.extern_c_fn_types.is_empty() {
+          "void"
+        } else {
+          &self.extern_c_fn_types
+        }
+      )
+      .as_str();
+      return self;
+    }
+
     self.output += format!(
-      "int32_t *{extern_c_fn_name}(int64_t port{extern_c_fn_types});",
+      "membrane_task_t {extern_c_fn_name}(int64_t port{extern_c_fn_types});",
       extern_c_fn_name = self.extern_c_fn_name,
       extern_c_fn_types = if self.extern_c_fn_types.is_empty() {
         String::new()
@@ -719,23 +1274,58 @@ impl Function {
       }
     )
     .as_str();
+
+    if self.dart_input_stream.is_some() {
+      self.output += format!(
+        "\nint32_t {push_fn}(membrane_task_t task, const uint8_t *data);",
+        push_fn = self.push_fn_name()
+      )
+      .as_str();
+    }
     self
   }
 
   pub fn body(&mut self, namespace: &str) -> &mut Self {
+    // synchronous functions return a buffer directly instead of a task handle + port
+    let (port, handle, call, handle_check) = if self.is_sync {
+      (
+        "",
+        "late final Pointer<Uint8> _buffer;",
+        format!(
+          "_buffer = _bindings.{extern_c_fn_name}({dart_inner_args});",
+          extern_c_fn_name = self.extern_c_fn_name,
+          dart_inner_args = self.dart_inner_args
+        ),
+        "_buffer.address == 0",
+      )
+    } else {
+      (
+        "\n      _port = ReceivePort();",
+        "late final ReceivePort _port;\n    late final int _taskHandle;",
+        format!(
+          "_taskHandle = _bindings.{extern_c_fn_name}(_port.sendPort.nativePort{dart_inner_args});",
+          extern_c_fn_name = self.extern_c_fn_name,
+          dart_inner_args = if self.dart_inner_args.is_empty() {
+            String::new()
+          } else {
+            String::from(", ") + &self.dart_inner_args
+          }
+        ),
+        "_taskHandle == 0",
+      )
+    };
+
     self.output += format!(
       r#" {{{disable_logging}
-    final List<Pointer> _toFree = [];{fn_transforms}
-    final _port = ReceivePort()..timeout(const Duration(milliseconds: 1000));
-
-    Pointer<Int32>? _taskHandle;
-    try {{
+    final List<Pointer> _toFree = [];
+    {handle}
+    try {{{fn_transforms}{port}
       if (!_loggingDisabled) {{
         _log.fine('Calling Rust `{fn_name}` via C `{extern_c_fn_name}`');
       }}
-      _taskHandle = _bindings.{extern_c_fn_name}(_port.sendPort.nativePort{dart_inner_args});
-      if (_taskHandle == null) {{
-        throw {class_name}ApiError('Call to C failed');
+      {call}
+      if ({handle_check}) {{
+        throw {class_name}ApiError(loader.lastErrorMessage() ?? 'Call to C failed');
       }}
     }} finally {{
       _toFree.forEach((ptr) => calloc.free(ptr));
@@ -752,15 +1342,14 @@ impl Function {
       fn_transforms = if self.dart_transforms.is_empty() {
         String::new()
       } else {
-        "\n    ".to_string() + &self.dart_transforms + ";"
+        "\n      ".to_string() + &self.dart_transforms + ";"
       },
+      port = port,
+      handle = handle,
+      call = call,
+      handle_check = handle_check,
       extern_c_fn_name = self.extern_c_fn_name,
       fn_name = self.fn_name,
-      dart_inner_args = if self.dart_inner_args.is_empty() {
-        String::new()
-      } else {
-        String::from(", ") + &self.dart_inner_args
-      },
       class_name = namespace.to_camel_case()
     )
     .as_str();
@@ -773,50 +1362,173 @@ impl Function {
     enum_tracer_registry: &Registry,
     config: &Membrane,
   ) -> &mut Self {
-    self.output += if self.is_stream {
+    let return_de = self.deserializer(&self.return_type, enum_tracer_registry, config);
+    // functions that don't return a `Result` never send the error tag
+    let error_case = match &self.error_type {
+      Some(error_type) => format!(
+        r#"
+        case 0:
+          throw {class_name}ApiError({error_de});"#,
+        error_de = self.deserializer(error_type, enum_tracer_registry, config),
+        class_name = namespace.to_camel_case(),
+      ),
+      None => String::new(),
+    };
+    let timeout_case = match self.timeout {
+      Some(timeout) => format!(
+        r#"
+        case 3:
+          throw TimeoutException('`{fn_name}` did not complete', {duration});"#,
+        fn_name = self.fn_name,
+        duration = duration(timeout),
+      ),
+      None => String::new(),
+    };
+    // items from a Dart `Stream` argument are pushed to Rust until the call completes
+    let (subscribe, unsubscribe) = match &self.dart_input_stream {
+      Some(listen) => (
+        format!(
+          r#"
+    void _push(Uint8List? data) {{
+      if (data == null) {{
+        _bindings.{push_fn}(_taskHandle, nullptr);
+        return;
+      }}
+      final List<Pointer> _toFree = [];
+      try {{
+        {frame}
+        _bindings.{push_fn}(_taskHandle, ptr);
+      }} finally {{
+        _toFree.forEach((ptr) => calloc.free(ptr));
+      }}
+    }}
+    final _inputSubscription = {listen};
+"#,
+          push_fn = self.push_fn_name(),
+          frame = membrane_types::dart::frame_partial("data"),
+          listen = listen
+        ),
+        "\n      await _inputSubscription.cancel();",
+      ),
+      None => (String::new(), ""),
+    };
+    let decode = format!(
+      r#"final tag = deserializer.deserializeUint8();
+      switch (tag) {{
+        case 1:
+          return {return_de};{error_case}
+        case 2:
+          throw MembranePanicError(deserializer.deserializeString());{timeout_case}
+        case 4:
+          throw MembraneCancelledError();
+        case 6:
+          throw MembraneArgumentError(
+              deserializer.deserializeString(), deserializer.deserializeString());
+      }}
+      throw StateError('Unknown tag $tag received from Rust');"#,
+      return_de = return_de,
+      error_case = error_case,
+      timeout_case = timeout_case,
+    );
+
+    self.output += if self.is_sync {
       format!(
         r#"
     try {{
-      yield* _port.map((input) {{
+      if (!_loggingDisabled) {{
+        _log.fine('Deserializing data from {fn_name}');
+      }}
+      final length = ByteData.sublistView(_buffer.asTypedList(8)).getUint64(0, Endian.little);
+      final deserializer = _Deserializer(_buffer.asTypedList(length).sublist(8));
+      {decode}
+    }} finally {{
+      _bindings.membrane_free_membrane_buffer(_buffer);
+    }}"#,
+        decode = decode,
+        fn_name = self.fn_name
+      )
+    } else if self.is_stream {
+      format!(
+        r#"{subscribe}
+    // Rust only polls for an item once it has a credit and holds it while the listener is paused
+    final _flow = StreamController(
+        onPause: () => _bindings.membrane_pause_membrane_task(_taskHandle),
+        onResume: () => _bindings.membrane_resume_membrane_task(_taskHandle));
+    _flow.addStream(_port).whenComplete(_flow.close);
+    _bindings.membrane_grant_credits(_taskHandle, {credits});
+    try {{
+      yield* _flow.stream.takeWhile((input) => !({done})).map((input) {{
+        _bindings.membrane_grant_credits(_taskHandle, 1);
         if (!_loggingDisabled) {{
           _log.fine('Deserializing data from {fn_name}');
         }}
-        final deserializer = BincodeDeserializer(input as Uint8List);
-        if (deserializer.deserializeBool()) {{
-          return {return_de};
-        }}
-        throw {class_name}ApiError({error_de});
+        {receive}
       }});
-    }} finally {{
-      if (_bindings.membrane_cancel_membrane_task(_taskHandle) < 1) {{
-        throw {class_name}ApiError('Cancelation call to C failed');
+    }} finally {{{unsubscribe}
+      _port.close();
+      // an unknown task has already finished or was canceled by `cancelAllTasks`
+      _bindings.membrane_cancel_membrane_task(_taskHandle);
+    }}"#,
+        receive = self.receive("input", &decode),
+        done = self.has_tag("input", 4),
+        credits = STREAM_CREDITS,
+        subscribe = subscribe,
+        unsubscribe = unsubscribe,
+        fn_name = self.fn_name
+      )
+    } else if let Some(progress_type) = &self.progress_type {
+      // progress frames are forwarded until the result arrives, a deadline is enforced by Rust alone here
+      format!(
+        r#"{subscribe}
+    try {{
+      await for (final input in _port) {{
+        if ({is_progress}) {{
+          final deserializer = _Deserializer({frame});
+          deserializer.deserializeUint8();
+          _progress.add({progress_de});
+          continue;
+        }}
+        if (!_loggingDisabled) {{
+          _log.fine('Deserializing data from {fn_name}');
+        }}
+        {receive}
       }}
+      throw StateError('`{fn_name}` ended without a result');
+    }} finally {{{unsubscribe}
+      _progress.close();
+      // an unknown task has already finished or was canceled by `cancelAllTasks`
+      _bindings.membrane_cancel_membrane_task(_taskHandle);
     }}"#,
-        return_de = self.deserializer(&self.return_type, enum_tracer_registry, config),
-        error_de = self.deserializer(&self.error_type, enum_tracer_registry, config),
-        class_name = namespace.to_camel_case(),
+        is_progress = self.has_tag("input", 5),
+        frame = self.frame("input"),
+        progress_de = self.deserializer(progress_type, enum_tracer_registry, config),
+        receive = self.receive("input", &decode),
+        subscribe = subscribe,
+        unsubscribe = unsubscribe,
         fn_name = self.fn_name
       )
     } else {
       format!(
-        r#"
+        r#"{subscribe}
     try {{
       if (!_loggingDisabled) {{
         _log.fine('Deserializing data from {fn_name}');
       }}
-      final deserializer = BincodeDeserializer(await _port.first as Uint8List);
-      if (deserializer.deserializeBool()) {{
-        return {return_de};
-      }}
-      throw {class_name}ApiError({error_de});
-    }} finally {{
-      if (_bindings.membrane_cancel_membrane_task(_taskHandle) < 1) {{
-        throw {class_name}ApiError('Cancelation call to C failed');
-      }}
+      {receive}
+    }} finally {{{unsubscribe}
+      // an unknown task has already finished or was canceled by `cancelAllTasks`
+      _bindings.membrane_cancel_membrane_task(_taskHandle);
     }}"#,
-        return_de = self.deserializer(&self.return_type, enum_tracer_registry, config),
-        error_de = self.deserializer(&self.error_type, enum_tracer_registry, config),
-        class_name = namespace.to_camel_case(),
+        receive = self.receive(
+          &match self.timeout {
+            // the Rust side also enforces the deadline, this covers a Rust task that can't be polled
+            Some(timeout) => format!("await _port.first.timeout({})", duration(timeout)),
+            None => "await _port.first".to_string(),
+          },
+          &decode
+        ),
+        subscribe = subscribe,
+        unsubscribe = unsubscribe,
         fn_name = self.fn_name
       )
     }
@@ -826,6 +1538,9 @@ impl Function {
   }
 
   pub fn end(&mut self) -> &mut Self {
+    if self.progress_type.is_some() {
+      self.output += "\n    }();\n    return WithProgress(_result, _progress.stream);";
+    }
     self.output += "\n  }\n";
     self
   }
@@ -837,56 +1552,883 @@ impl Function {
     self
   }
 
+  fn receive(&self, input: &str, decode: &str) -> String {
+    if !is_byte_buffer(&self.return_type) {
+      return format!(
+        r#"final deserializer = _Deserializer({input} as Uint8List);
+      {decode}"#,
+        input = input,
+        decode = decode
+      );
+    }
+
+    // byte buffers arrive without any bincode framing while every other frame arrives wrapped in a list
+    format!(
+      r#"final _data = {input};
+      if (_data is Uint8List) {{
+        return _data;
+      }}
+      final deserializer = _Deserializer((_data as List).first as Uint8List);
+      {decode}"#,
+      input = input,
+      decode = decode
+    )
+  }
+
+  /// Checks the leading tag of a frame, byte buffer frames other than the value itself arrive wrapped in a list
+  fn has_tag(&self, input: &str, tag: u8) -> String {
+    if !is_byte_buffer(&self.return_type) {
+      return format!(
+        "({input} as Uint8List).first == {tag}",
+        input = input,
+        tag = tag
+      );
+    }
+
+    format!(
+      "{input} is! Uint8List && (({input} as List).first as Uint8List).first == {tag}",
+      input = input,
+      tag = tag
+    )
+  }
+
+  fn frame(&self, input: &str) -> String {
+    if !is_byte_buffer(&self.return_type) {
+      return format!("{input} as Uint8List", input = input);
+    }
+
+    format!("({input} as List).first as Uint8List", input = input)
+  }
+
   fn deserializer(&self, ty: &str, enum_tracer_registry: &Registry, config: &Membrane) -> String {
-    let de;
-    match ty {
-      "String" => "deserializer.deserializeString()",
-      "i32" => "deserializer.deserializeInt32()",
-      "i64" => "deserializer.deserializeInt64()",
-      "f32" => "deserializer.deserializeFloat32()",
-      "f64" => "deserializer.deserializeFloat64()",
-      "bool" => "deserializer.deserializeBool()",
-      "()" => "null",
-      ty if ty == "Option" => {
-        panic!(
-          "Option is not supported as a bare return type. Return the inner type from {} instead",
-          self.fn_name
-        )
-      }
-      _ => {
-        de = match enum_tracer_registry.get(ty) {
-          Some(ContainerFormat::Enum { .. }) if config.c_style_enums => {
-            format!("{}Extension.deserialize(deserializer)", ty)
-          }
-          _ => format!("{}.deserialize(deserializer)", ty),
-        };
-        &de
+    dart_deserializer(ty, &|name| match enum_tracer_registry.get(name) {
+      Some(ContainerFormat::Enum { .. }) if config.c_style_enums => {
+        format!("{}Extension.deserialize(deserializer)", name)
       }
-    }
-    .to_string()
+      _ => format!("{}.deserialize(deserializer)", name),
+    })
   }
 }
 
-#[doc(hidden)]
-pub struct TaskHandle(pub ::futures::future::AbortHandle);
+///
+/// The wire format of the values passed between Dart and Rust. Functions are compiled for one with
+/// `encoding = "bcs"` and the package is generated for the same one with `Membrane::with_encoding`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  /// Compact and fast, the default
+  Bincode,
+  /// Binary Canonical Serialization gives every value exactly one encoding. It doesn't support floats.
+  Bcs,
+}
 
-#[doc(hidden)]
-#[no_mangle]
-pub unsafe extern "C" fn membrane_cancel_membrane_task(task_handle: *mut TaskHandle) -> i32 {
-  // turn the pointer back into a box and Rust will drop it when it goes out of scope
-  let handle = Box::from_raw(task_handle);
-  handle.0.abort();
+impl Encoding {
+  /// The name of the Dart runtime that serde-generate installs for the encoding
+  fn runtime(self) -> &'static str {
+    match self {
+      Encoding::Bincode => "bincode",
+      Encoding::Bcs => "bcs",
+    }
+  }
+}
 
-  1
+impl From<Encoding> for serde_generate::Encoding {
+  fn from(encoding: Encoding) -> Self {
+    match encoding {
+      Encoding::Bincode => serde_generate::Encoding::Bincode,
+      Encoding::Bcs => serde_generate::Encoding::Bcs,
+    }
+  }
 }
 
+/// Serializes with the encoding that a function was compiled for
 #[doc(hidden)]
-#[macro_export]
-macro_rules! error {
-  ($result:expr) => {
-    error!($result, ::std::ptr::null());
-  };
-  ($result:expr, $error:expr) => {
+pub mod encoding {
+  use super::Encoding;
+
+  #[derive(Debug)]
+  pub struct EncodingError(String);
+
+  impl std::fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{}", self.0)
+    }
+  }
+
+  impl std::error::Error for EncodingError {}
+
+  pub fn serialize<T: ?Sized + serde::Serialize>(
+    encoding: Encoding,
+    value: &T,
+  ) -> Result<Vec<u8>, EncodingError> {
+    match encoding {
+      Encoding::Bincode => bincode::serialize(value).map_err(|err| EncodingError(err.to_string())),
+      Encoding::Bcs => bcs::to_bytes(value).map_err(|err| EncodingError(err.to_string())),
+    }
+  }
+
+  pub fn deserialize<T: serde::de::DeserializeOwned>(
+    encoding: Encoding,
+    bytes: &[u8],
+  ) -> Result<T, EncodingError> {
+    match encoding {
+      Encoding::Bincode => {
+        bincode::deserialize(bytes).map_err(|err| EncodingError(err.to_string()))
+      }
+      Encoding::Bcs => bcs::from_bytes(bytes).map_err(|err| EncodingError(err.to_string())),
+    }
+  }
+
+  /// Serializes a frame that's posted to Dart, one that can't be serialized (such as a float with
+  /// BCS) is replaced by a panic frame with the reason so that Dart isn't left waiting
+  pub fn frame<T: ?Sized + serde::Serialize>(encoding: Encoding, frame: &T) -> Vec<u8> {
+    serialize(encoding, frame).unwrap_or_else(|err| {
+      let message = format!("the result couldn't be serialized, {}", err);
+      serialize(encoding, &(crate::frame::PANIC, message)).unwrap_or_default()
+    })
+  }
+}
+
+/// The schema hash of each namespace, tracing every type is slow so it's only done once
+static SCHEMA_HASHES: once_cell::sync::Lazy<Result<HashMap<&'static str, u64>, String>> =
+  once_cell::sync::Lazy::new(|| std::panic::catch_unwind(schema_hashes).map_err(panic_message));
+
+fn schema_hashes() -> HashMap<&'static str, u64> {
+  let mut namespaces = HashMap::new();
+  // enums are traced first so that the functions using them can be traced
+  let (enums, functions): (Vec<&DeferredSchema>, _) = inventory::iter::<DeferredSchema>
+    .into_iter()
+    .partition(|item| item.is_enum);
+  for item in enums.into_iter().chain(functions) {
+    let (tracer, samples, fingerprints) = namespaces.entry(item.namespace).or_insert_with(|| {
+      (
+        Tracer::new(TracerConfig::default()),
+        Samples::new(),
+        Vec::new(),
+      )
+    });
+    (item.trace)(tracer, samples);
+    if !item.is_enum {
+      fingerprints.push(item.fingerprint);
+    }
+  }
+
+  namespaces
+    .into_iter()
+    .map(|(namespace, (tracer, _, mut fingerprints))| {
+      // inventory's order depends on linking so the generator and the library can differ
+      fingerprints.sort_unstable();
+      let schema = format!("{:?}{:?}", tracer.registry(), fingerprints);
+      (namespace, membrane_types::fnv1a(schema.as_bytes()))
+    })
+    .collect()
+}
+
+#[derive(Debug)]
+struct SchemaError(String);
+
+impl std::fmt::Display for SchemaError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Returned by `membrane_schema_hash` when the library can't be checked, because it was built with
+/// the `skip-generate` feature or because its types couldn't be traced
+const UNCHECKED_SCHEMA: u64 = u64::MAX;
+
+/// A fingerprint of the types, function signatures and encoding of a namespace, or 0 when nothing
+/// was traced for it
+fn schema_hash(namespace: &str) -> u64 {
+  match &*SCHEMA_HASHES {
+    Ok(hashes) => hashes.get(namespace).copied().unwrap_or(0),
+    Err(_) => 0,
+  }
+}
+
+/// Compared by the generated loader with the hash of the namespace it was generated from, 0 for a
+/// namespace the library doesn't have is a mismatch. `UNCHECKED_SCHEMA` skips the check, when the
+/// types couldn't be traced the reason is kept as the last error.
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn membrane_schema_hash(ns: *const std::os::raw::c_char) -> u64 {
+  if cfg!(feature = "skip-generate") {
+    return UNCHECKED_SCHEMA;
+  }
+  if ns.is_null() {
+    return 0;
+  }
+
+  let hash = std::panic::catch_unwind(|| {
+    let namespace = std::ffi::CStr::from_ptr(ns).to_str().unwrap_or_default();
+    SCHEMA_HASHES
+      .as_ref()
+      .map(|_| schema_hash(namespace))
+      .map_err(|message| format!("the schema hash couldn't be computed, {}", message))
+  });
+
+  match hash {
+    Ok(Ok(hash)) => hash,
+    Ok(Err(message)) => {
+      ffi_helpers::update_last_error(SchemaError(message));
+      UNCHECKED_SCHEMA
+    }
+    Err(_) => UNCHECKED_SCHEMA,
+  }
+}
+
+/// The tag that leads every message sent to Dart
+#[doc(hidden)]
+pub mod frame {
+  pub const ERR: u8 = 0;
+  pub const OK: u8 = 1;
+  pub const PANIC: u8 = 2;
+  pub const TIMEOUT: u8 = 3;
+  pub const DONE: u8 = 4;
+  pub const PROGRESS: u8 = 5;
+  pub const ARGUMENT: u8 = 6;
+}
+
+static MAX_ARGUMENT_LENGTH: std::sync::atomic::AtomicU64 =
+  std::sync::atomic::AtomicU64::new(256 * 1024 * 1024);
+
+///
+/// Sets the largest serialized argument, in bytes, that Rust accepts from Dart. The default is 256MiB,
+/// a call with a larger argument fails instead of copying it.
+///
+pub fn set_max_argument_length(bytes: u64) {
+  MAX_ARGUMENT_LENGTH.store(bytes, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Why an argument from Dart couldn't be converted, Dart throws it as a `MembraneArgumentError`
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ArgumentError {
+  pub parameter: String,
+  pub message: String,
+}
+
+impl ArgumentError {
+  pub fn new(parameter: &str, message: impl ToString) -> Self {
+    Self {
+      parameter: parameter.to_string(),
+      message: message.to_string(),
+    }
+  }
+}
+
+impl std::fmt::Display for ArgumentError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Invalid argument '{}': {}", self.parameter, self.message)
+  }
+}
+
+impl std::error::Error for ArgumentError {}
+
+///
+/// Reads the payload of an argument frame written by Dart: a magic byte followed by the little
+/// endian u64 length of the payload and then the payload itself.
+///
+/// # Safety
+/// `ptr` must be null or point to at least `ARG_FRAME_HEADER_LENGTH` readable bytes, and to the whole
+/// frame when the magic byte is valid.
+///
+#[doc(hidden)]
+pub unsafe fn read_argument_frame<'a>(
+  ptr: *const u8,
+  parameter: &str,
+) -> Result<&'a [u8], ArgumentError> {
+  if ptr.is_null() {
+    return Err(ArgumentError::new(parameter, "no data was received"));
+  }
+
+  let header = std::slice::from_raw_parts(ptr, membrane_types::ARG_FRAME_HEADER_LENGTH);
+  if header[0] != membrane_types::ARG_FRAME_MAGIC {
+    return Err(ArgumentError::new(
+      parameter,
+      format!(
+        "the data isn't a valid argument frame (found a magic byte of {:#04x} instead of {:#04x}), is the Dart package out of date?",
+        header[0],
+        membrane_types::ARG_FRAME_MAGIC
+      ),
+    ));
+  }
+
+  let mut length = [0; 8];
+  length.copy_from_slice(&header[1..]);
+  let length = u64::from_le_bytes(length);
+  let max = MAX_ARGUMENT_LENGTH.load(std::sync::atomic::Ordering::Relaxed);
+  if length > max {
+    return Err(ArgumentError::new(
+      parameter,
+      format!(
+        "{} bytes exceeds the maximum argument length of {} bytes",
+        length, max
+      ),
+    ));
+  }
+
+  Ok(std::slice::from_raw_parts(
+    ptr.add(membrane_types::ARG_FRAME_HEADER_LENGTH),
+    length as usize,
+  ))
+}
+
+///
+/// Copies a string argument out of Dart owned memory.
+///
+/// # Safety
+/// `ptr` must be null or point to a nul terminated string.
+///
+#[doc(hidden)]
+pub unsafe fn read_argument_string(
+  ptr: *const std::os::raw::c_char,
+  parameter: &str,
+) -> Result<String, ArgumentError> {
+  if ptr.is_null() {
+    return Err(ArgumentError::new(parameter, "no data was received"));
+  }
+
+  std::ffi::CStr::from_ptr(ptr)
+    .to_str()
+    .map(str::to_string)
+    .map_err(|err| ArgumentError::new(parameter, format!("the string isn't valid UTF-8, {}", err)))
+}
+
+#[doc(hidden)]
+pub fn deserialize_argument<T: serde::de::DeserializeOwned>(
+  encoding: Encoding,
+  data: &[u8],
+  parameter: &str,
+  rust_type: &str,
+) -> Result<T, ArgumentError> {
+  encoding::deserialize(encoding, data).map_err(|err| {
+    ArgumentError::new(
+      parameter,
+      format!(
+        "the data couldn't be deserialized as a `{}`, {}",
+        rust_type, err
+      ),
+    )
+  })
+}
+
+#[doc(hidden)]
+pub fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+  // `panic!` payloads are either a static string or a formatted `String`
+  match panic.downcast::<String>() {
+    Ok(message) => *message,
+    Err(panic) => match panic.downcast::<&'static str>() {
+      Ok(message) => message.to_string(),
+      Err(_) => "unknown panic payload".to_string(),
+    },
+  }
+}
+
+/// How many items a stream can send to Dart before the listener has received any of them
+const STREAM_CREDITS: u64 = 16;
+
+fn duration(timeout: u64) -> String {
+  format!("const Duration(milliseconds: {})", timeout)
+}
+
+#[doc(hidden)]
+pub struct TaskHandle {
+  pub abort: ::futures::future::AbortHandle,
+  /// Receives the items that Dart pushes into a stream argument
+  pub sender: Option<::futures::channel::mpsc::UnboundedSender<Vec<u8>>>,
+  pub token: Option<CancellationToken>,
+  pub flow: Option<std::sync::Arc<FlowControl>>,
+}
+
+impl TaskHandle {
+  pub fn new(abort: ::futures::future::AbortHandle) -> Self {
+    Self {
+      abort,
+      sender: None,
+      token: None,
+      flow: None,
+    }
+  }
+}
+
+///
+/// Credit based flow control between a Rust stream and its Dart listener. Dart grants a credit for
+/// every item it's ready to receive and the stream isn't polled for an item without one, an item
+/// that's ready while the listener is paused is held until it resumes.
+///
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct FlowControl {
+  credits: std::sync::atomic::AtomicU64,
+  paused: std::sync::atomic::AtomicBool,
+  waker: futures::task::AtomicWaker,
+}
+
+impl FlowControl {
+  pub fn grant(&self, credits: u64) {
+    let _ = self.credits.fetch_update(
+      std::sync::atomic::Ordering::SeqCst,
+      std::sync::atomic::Ordering::SeqCst,
+      |available| Some(available.saturating_add(credits)),
+    );
+    self.waker.wake();
+  }
+
+  pub fn pause(&self) {
+    self.paused.store(true, std::sync::atomic::Ordering::SeqCst);
+  }
+
+  pub fn resume(&self) {
+    self
+      .paused
+      .store(false, std::sync::atomic::Ordering::SeqCst);
+    self.waker.wake();
+  }
+
+  /// Stops limiting the stream, a cancelled stream has to be polled again to notice it
+  pub fn release(&self) {
+    self
+      .credits
+      .store(u64::MAX, std::sync::atomic::Ordering::SeqCst);
+    self.resume();
+  }
+
+  /// Resolves once a credit is available and takes it
+  pub fn acquire(&self) -> impl std::future::Future<Output = ()> + '_ {
+    futures::future::poll_fn(move |cx| {
+      self.waker.register(cx.waker());
+      let taken = self.credits.fetch_update(
+        std::sync::atomic::Ordering::SeqCst,
+        std::sync::atomic::Ordering::SeqCst,
+        |available| available.checked_sub(1),
+      );
+      match taken {
+        Ok(_) => std::task::Poll::Ready(()),
+        Err(_) => std::task::Poll::Pending,
+      }
+    })
+  }
+
+  /// Resolves once the listener isn't paused
+  pub fn ready(&self) -> impl std::future::Future<Output = ()> + '_ {
+    futures::future::poll_fn(move |cx| {
+      self.waker.register(cx.waker());
+      match self.paused.load(std::sync::atomic::Ordering::SeqCst) {
+        true => std::task::Poll::Pending,
+        false => std::task::Poll::Ready(()),
+      }
+    })
+  }
+}
+
+/// Every task that is still running along with its namespace, Dart holds the ID rather than a
+/// pointer to the handle
+static TASKS: std::sync::Mutex<std::collections::BTreeMap<u64, (&str, TaskHandle)>> =
+  std::sync::Mutex::new(std::collections::BTreeMap::new());
+static NEXT_TASK_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+/// Returned when a task ID isn't known, such as after a double cancel or a hot restart
+const UNKNOWN_TASK: i32 = -1;
+/// Returned when pushed data isn't a valid argument frame, the reason is kept as the last error
+const INVALID_FRAME: i32 = -2;
+
+fn tasks(
+) -> std::sync::MutexGuard<'static, std::collections::BTreeMap<u64, (&'static str, TaskHandle)>> {
+  TASKS
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[doc(hidden)]
+pub fn register_task(namespace: &'static str, handle: TaskHandle) -> u64 {
+  let id = NEXT_TASK_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  tasks().insert(id, (namespace, handle));
+  id
+}
+
+/// The ID of a call that was rejected before its task was spawned, Dart's cleanup treats it like
+/// a task that has already finished
+#[doc(hidden)]
+pub fn finished_task() -> u64 {
+  NEXT_TASK_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Held by a spawned task so that it's forgotten once it returns or is aborted
+#[doc(hidden)]
+pub struct TaskGuard(pub u64);
+
+impl Drop for TaskGuard {
+  fn drop(&mut self) {
+    // the handle is dropped after the lock is released
+    let _handle = tasks().remove(&self.0);
+  }
+}
+
+/// Takes what's needed to cancel a task from the registry. A task with a cancellation token stays
+/// registered until it returns or its grace period is over, cancelling it again aborts it.
+fn cancellation(
+  tasks: &mut std::collections::BTreeMap<u64, (&'static str, TaskHandle)>,
+  task: u64,
+) -> Option<TaskHandle> {
+  let (_, handle) = tasks.get_mut(&task)?;
+  match &handle.token {
+    Some(token) if !token.is_cancelled() => Some(TaskHandle {
+      abort: handle.abort.clone(),
+      // a stream argument ends with the cancellation
+      sender: handle.sender.take(),
+      token: Some(token.clone()),
+      flow: handle.flow.clone(),
+    }),
+    _ => tasks.remove(&task).map(|(_, handle)| handle),
+  }
+}
+
+fn cancel_task(handle: TaskHandle) {
+  if let Some(flow_control) = &handle.flow {
+    flow_control.release();
+  }
+  match &handle.token {
+    // the task aborts itself if it's still running once the grace period is over
+    Some(token) if !token.is_cancelled() => token.cancel(),
+    _ => handle.abort.abort(),
+  }
+}
+
+/// A null `ns` matches every namespace, an invalid one matches none
+unsafe fn namespace_filter(ns: *const std::os::raw::c_char) -> impl Fn(&str) -> bool {
+  let namespace = match ns.is_null() {
+    true => None,
+    false => Some(std::ffi::CStr::from_ptr(ns).to_str().map(str::to_string)),
+  };
+  move |task_namespace| match &namespace {
+    None => true,
+    Some(Ok(namespace)) => namespace == task_namespace,
+    Some(Err(_)) => false,
+  }
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn membrane_cancel_membrane_task(task: u64) -> i32 {
+  let handle = cancellation(&mut tasks(), task);
+  match handle {
+    Some(handle) => cancel_task(handle),
+    None => return UNKNOWN_TASK,
+  };
+
+  1
+}
+
+/// Cancels the tasks of a namespace (or of every namespace when `ns` is null) and returns how many
+/// there were, tasks left running by a Dart hot restart can be cleaned up this way
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn membrane_cancel_all_tasks(ns: *const std::os::raw::c_char) -> i32 {
+  let matches = namespace_filter(ns);
+  // one lock so that tasks of other namespaces finishing meanwhile are still forgotten
+  let cancelled: Vec<_> = {
+    let mut tasks = tasks();
+    let ids: Vec<u64> = tasks
+      .iter()
+      .filter(|(_, (namespace, _))| matches(namespace))
+      .map(|(id, _)| *id)
+      .collect();
+    ids
+      .into_iter()
+      .filter_map(|id| cancellation(&mut tasks, id))
+      .collect()
+  };
+
+  let count = cancelled.len();
+  cancelled.into_iter().for_each(cancel_task);
+  count as i32
+}
+
+/// The number of tasks that are still running in a namespace, or in every namespace when `ns` is
+/// null
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn membrane_active_task_count(ns: *const std::os::raw::c_char) -> i32 {
+  let matches = namespace_filter(ns);
+  tasks()
+    .values()
+    .filter(|(namespace, _)| matches(namespace))
+    .count() as i32
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn membrane_pause_membrane_task(task: u64) -> i32 {
+  match tasks().get(&task).map(|(_, handle)| handle.flow.as_ref()) {
+    Some(Some(flow_control)) => {
+      flow_control.pause();
+      1
+    }
+    Some(None) => 0,
+    None => UNKNOWN_TASK,
+  }
+}
+
+/// Lets a stream send `credits` more items to Dart
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn membrane_grant_credits(task: u64, credits: u64) -> i32 {
+  match tasks().get(&task).map(|(_, handle)| handle.flow.as_ref()) {
+    Some(Some(flow_control)) => {
+      flow_control.grant(credits);
+      1
+    }
+    Some(None) => 0,
+    None => UNKNOWN_TASK,
+  }
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn membrane_resume_membrane_task(task: u64) -> i32 {
+  match tasks().get(&task).map(|(_, handle)| handle.flow.as_ref()) {
+    Some(Some(flow_control)) => {
+      flow_control.resume();
+      1
+    }
+    Some(None) => 0,
+    None => UNKNOWN_TASK,
+  }
+}
+
+#[doc(hidden)]
+pub unsafe fn push_to_task(task: u64, data: *const u8) -> i32 {
+  let tasks = tasks();
+  let sender = match tasks.get(&task).map(|(_, handle)| handle.sender.as_ref()) {
+    Some(Some(sender)) => sender,
+    Some(None) => return 0,
+    None => return UNKNOWN_TASK,
+  };
+
+  // a null pointer means that the Dart stream is done
+  if data.is_null() {
+    sender.close_channel();
+    return 1;
+  }
+
+  let data = match read_argument_frame(data, "stream item") {
+    Ok(data) => data,
+    Err(err) => {
+      ffi_helpers::update_last_error(err);
+      return INVALID_FRAME;
+    }
+  };
+
+  match sender.unbounded_send(data.to_vec()) {
+    Ok(()) => 1,
+    // the task has already finished
+    Err(_) => 0,
+  }
+}
+
+///
+/// Fans the values of a channel static out to every Dart subscription, used by `broadcast = true`.
+///
+#[doc(hidden)]
+pub struct Broadcast<T> {
+  state: ::std::sync::Mutex<BroadcastState<T>>,
+}
+
+struct BroadcastState<T> {
+  /// The task ID of the forwarder while one is running
+  forwarder: Option<u64>,
+  next_subscriber: u64,
+  subscribers: std::collections::BTreeMap<u64, ::futures::channel::mpsc::UnboundedSender<T>>,
+  last: Option<T>,
+}
+
+impl<T> Broadcast<T> {
+  pub const fn new() -> Self {
+    Self {
+      state: ::std::sync::Mutex::new(BroadcastState {
+        forwarder: None,
+        next_subscriber: 0,
+        subscribers: std::collections::BTreeMap::new(),
+        last: None,
+      }),
+    }
+  }
+
+  /// Ends every subscription once the channel has closed or the forwarder was canceled, a
+  /// forwarder that was already replaced or stopped for lack of subscribers changes nothing
+  pub fn close(&self, forwarder: u64) {
+    let mut state = self.state();
+    if state.forwarder == Some(forwarder) {
+      state.subscribers.clear();
+      state.forwarder = None;
+    }
+  }
+
+  fn unsubscribe(&self, subscriber: u64) {
+    let forwarder = {
+      let mut state = self.state();
+      state.subscribers.remove(&subscriber);
+      match state.subscribers.is_empty() {
+        true => state.forwarder.take(),
+        false => None,
+      }
+    };
+    // nobody is listening anymore so the forwarder doesn't need to hold on to its task
+    if let Some(forwarder) = forwarder {
+      membrane_cancel_membrane_task(forwarder);
+    }
+  }
+
+  fn state(&self) -> ::std::sync::MutexGuard<'_, BroadcastState<T>> {
+    // the state stays consistent even if a subscriber panicked while holding the lock
+    self
+      .state
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+}
+
+///
+/// Every subscription gets its own copy of a `broadcast = true` channel's values, so they must be `Clone`.
+///
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+  message = "`broadcast = true` requires `{Self}` to implement `Clone`, both the value and error type of a `Result` channel",
+  label = "every subscription receives a clone of this value"
+)]
+pub trait BroadcastValue: Clone {}
+
+impl<T: Clone> BroadcastValue for T {}
+
+impl<T: BroadcastValue> Broadcast<T> {
+  /// Adds a subscriber, `forward` is called to start a forwarder from the channel and return its
+  /// task ID when none is running
+  pub fn subscribe(
+    &'static self,
+    replay: bool,
+    forward: impl FnOnce() -> u64,
+  ) -> BroadcastReceiver<T> {
+    let (sender, receiver) = ::futures::channel::mpsc::unbounded();
+    let mut state = self.state();
+    if let (true, Some(last)) = (replay, &state.last) {
+      let _ = sender.unbounded_send(last.clone());
+    }
+    let subscriber = state.next_subscriber;
+    state.next_subscriber += 1;
+    state.subscribers.insert(subscriber, sender);
+    if state.forwarder.is_none() {
+      state.forwarder = Some(forward());
+    }
+
+    BroadcastReceiver {
+      broadcast: self,
+      subscriber,
+      receiver,
+    }
+  }
+
+  pub fn publish(&self, value: T) {
+    let mut state = self.state();
+    state
+      .subscribers
+      .retain(|_, subscriber| subscriber.unbounded_send(value.clone()).is_ok());
+    state.last = Some(value);
+  }
+}
+
+/// The values of a single subscription, it's unsubscribed when dropped
+#[doc(hidden)]
+pub struct BroadcastReceiver<T: 'static> {
+  broadcast: &'static Broadcast<T>,
+  subscriber: u64,
+  receiver: ::futures::channel::mpsc::UnboundedReceiver<T>,
+}
+
+impl<T> ::futures::Stream for BroadcastReceiver<T> {
+  type Item = T;
+
+  fn poll_next(
+    mut self: std::pin::Pin<&mut Self>,
+    cx: &mut std::task::Context<'_>,
+  ) -> std::task::Poll<Option<T>> {
+    ::futures::Stream::poll_next(std::pin::Pin::new(&mut self.receiver), cx)
+  }
+}
+
+impl<T> Drop for BroadcastReceiver<T> {
+  fn drop(&mut self) {
+    self.broadcast.unsubscribe(self.subscriber);
+  }
+}
+
+impl<T> Default for Broadcast<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[doc(hidden)]
+pub fn into_membrane_buffer(data: Vec<u8>) -> *const u8 {
+  // prefix the payload with the length of the whole buffer (including the 8 byte prefix)
+  // so that Dart knows how much to read and so that we know how much to free later
+  let mut buffer = Vec::with_capacity(data.len() + 8);
+  buffer.extend_from_slice(&((data.len() + 8) as u64).to_le_bytes());
+  buffer.extend(data);
+  Box::into_raw(buffer.into_boxed_slice()) as *const u8
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn membrane_free_membrane_buffer(buffer: *mut u8) -> i32 {
+  if buffer.is_null() {
+    return 0;
+  }
+
+  let mut length = [0u8; 8];
+  length.copy_from_slice(std::slice::from_raw_parts(buffer, 8));
+  let length = u64::from_le_bytes(length) as usize;
+  // turn the pointer back into a boxed slice and Rust will drop it when it goes out of scope
+  drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+    buffer, length,
+  )));
+
+  1
+}
+
+/// The length in bytes (including the nul terminator) of the last error on this thread, or 0
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn membrane_last_error_length() -> i32 {
+  ffi_helpers::error_handling::last_error_length()
+}
+
+/// Copies the last error on this thread into `buffer` and clears it so that it isn't reported again
+/// for a later failure. Returns the number of bytes written, 0 without an error or -1 when
+/// `buffer` is too short.
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn membrane_last_error_message(
+  buffer: *mut std::os::raw::c_char,
+  length: i32,
+) -> i32 {
+  let written = ffi_helpers::error_handling::error_message_utf8(buffer, length);
+  if written > 0 {
+    ffi_helpers::take_last_error();
+  }
+  written
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! error {
+  // a null pointer, or zero for functions returning a task ID
+  ($result:expr) => {
+    error!($result, ::membrane::ffi_helpers::Nullable::NULL);
+  };
+  ($result:expr, $error:expr) => {
     match $result {
       Ok(value) => value,
       Err(e) => {
@@ -901,7 +2443,7 @@ macro_rules! error {
 #[macro_export]
 macro_rules! cstr {
   ($ptr:expr) => {
-    cstr!($ptr, ::std::ptr::null())
+    cstr!($ptr, ::membrane::ffi_helpers::Nullable::NULL)
   };
   ($ptr:expr, $error:expr) => {{
     ::membrane::ffi_helpers::null_pointer_check!($ptr);
@@ -911,10 +2453,16 @@ macro_rules! cstr {
 
 #[cfg(test)]
 mod tests {
+  use futures::{FutureExt, StreamExt};
   use std::env::{remove_var, set_var};
   use std::path::PathBuf;
 
-  use crate::Membrane;
+  use crate::{
+    encoding, membrane_active_task_count, membrane_cancel_all_tasks, membrane_cancel_membrane_task,
+    membrane_grant_credits, membrane_last_error_length, membrane_last_error_message,
+    membrane_pause_membrane_task, read_argument_frame, register_task, Broadcast, CancellationToken,
+    Encoding, FlowControl, Membrane, TaskHandle,
+  };
 
   #[test]
   fn test_envars_are_used() {
@@ -923,13 +2471,15 @@ mod tests {
     assert_eq!(project.destination, PathBuf::from("membrane_output"));
     assert_eq!(project.library, "libmembrane");
     assert!(project.llvm_paths.is_empty());
+    assert_eq!(project.encoding(), Encoding::Bincode);
 
     set_var("MEMBRANE_PACKAGE_NAME", "a_package");
     set_var("MEMBRANE_DESTINATION", "./this_dir");
     set_var("MEMBRANE_LIBRARY", "libcustom");
     set_var("MEMBRANE_LLVM_PATHS", "/usr/lib/opt/foo,/usr/lib/opt/bar");
+    set_var("MEMBRANE_ENCODING", "bcs");
 
-    let project2 = Membrane::new();
+    let mut project2 = Membrane::new();
     assert_eq!(project2.package_name, "a_package");
     assert_eq!(project2.destination, PathBuf::from("./this_dir"));
     assert_eq!(project2.library, "libcustom");
@@ -937,10 +2487,262 @@ mod tests {
       project2.llvm_paths,
       vec!["/usr/lib/opt/foo", "/usr/lib/opt/bar"]
     );
+    assert_eq!(
+      project2.with_encoding(Encoding::Bincode).encoding(),
+      Encoding::Bcs
+    );
 
     remove_var("MEMBRANE_PACKAGE_NAME");
     remove_var("MEMBRANE_DESTINATION");
     remove_var("MEMBRANE_LIBRARY");
     remove_var("MEMBRANE_LLVM_PATHS");
+    remove_var("MEMBRANE_ENCODING");
+  }
+
+  #[test]
+  fn test_cancelled_futures_keep_a_single_waker() {
+    use std::future::Future;
+
+    let token = CancellationToken::new();
+    let waker = futures::task::noop_waker();
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut cancelled = Box::pin(token.cancelled());
+    for _ in 0..3 {
+      assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+    }
+    assert_eq!(token.wakers().len(), 1);
+
+    drop(cancelled);
+    assert!(token.wakers().is_empty());
+
+    let mut cancelled = Box::pin(token.cancelled());
+    assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+    token.cancel();
+    assert!(cancelled.as_mut().poll(&mut cx).is_ready());
+  }
+
+  #[test]
+  fn test_streams_wait_for_credits() {
+    use std::future::Future;
+
+    let flow_control = FlowControl::default();
+    let waker = futures::task::noop_waker();
+    let mut cx = std::task::Context::from_waker(&waker);
+    assert!(Box::pin(flow_control.acquire())
+      .as_mut()
+      .poll(&mut cx)
+      .is_pending());
+
+    flow_control.grant(2);
+    flow_control.pause();
+    assert!(Box::pin(flow_control.ready())
+      .as_mut()
+      .poll(&mut cx)
+      .is_pending());
+    for _ in 0..2 {
+      assert!(Box::pin(flow_control.acquire())
+        .as_mut()
+        .poll(&mut cx)
+        .is_ready());
+    }
+    assert!(Box::pin(flow_control.acquire())
+      .as_mut()
+      .poll(&mut cx)
+      .is_pending());
+
+    flow_control.resume();
+    assert!(Box::pin(flow_control.ready())
+      .as_mut()
+      .poll(&mut cx)
+      .is_ready());
+  }
+
+  #[test]
+  fn test_task_ids_are_only_valid_until_cancelled() {
+    let (abort, _) = futures::future::AbortHandle::new_pair();
+    let task = register_task("tests", TaskHandle::new(abort));
+
+    assert_eq!(membrane_pause_membrane_task(task), 0);
+    assert_eq!(membrane_grant_credits(task, 1), 0);
+    assert_eq!(membrane_cancel_membrane_task(task), 1);
+    assert_eq!(membrane_cancel_membrane_task(task), -1);
+    assert_eq!(membrane_pause_membrane_task(task), -1);
+    assert_eq!(membrane_cancel_membrane_task(0), -1);
+  }
+
+  #[test]
+  fn test_a_task_is_counted_during_its_grace_period() {
+    let ns = std::ffi::CString::new("grace").unwrap();
+    let token = CancellationToken::new();
+    let (abort, registration) = futures::future::AbortHandle::new_pair();
+    let task = register_task(
+      "grace",
+      TaskHandle {
+        token: Some(token.clone()),
+        ..TaskHandle::new(abort)
+      },
+    );
+
+    unsafe {
+      assert_eq!(membrane_cancel_all_tasks(ns.as_ptr()), 1);
+      assert!(token.is_cancelled());
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 1);
+
+      // cancelling it again doesn't wait for the grace period
+      assert_eq!(membrane_cancel_membrane_task(task), 1);
+      let aborted = futures::future::Abortable::new(futures::future::pending::<()>(), registration);
+      assert!(matches!(
+        futures::FutureExt::now_or_never(aborted),
+        Some(Err(_))
+      ));
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 0);
+    }
+    assert_eq!(membrane_cancel_membrane_task(task), -1);
+  }
+
+  #[test]
+  fn test_only_the_bytes_crate_is_a_byte_buffer() {
+    assert!(membrane_types::is_byte_buffer("Vec<u8>"));
+    assert!(membrane_types::is_byte_buffer("bytes::Bytes"));
+    assert!(membrane_types::is_byte_buffer(":: bytes :: Bytes"));
+    assert!(!membrane_types::is_byte_buffer("Bytes"));
+    assert!(!membrane_types::is_byte_buffer("data::Bytes"));
+    assert!(!membrane_types::is_byte_buffer("Vec<i8>"));
+  }
+
+  #[test]
+  fn test_argument_frames_are_validated() {
+    let payload: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let mut frame = vec![membrane_types::ARG_FRAME_MAGIC];
+    frame.extend((payload.len() as u64).to_le_bytes());
+    frame.extend(&payload);
+
+    unsafe {
+      assert_eq!(read_argument_frame(frame.as_ptr(), "arg").unwrap(), payload);
+
+      let mut oversized = vec![membrane_types::ARG_FRAME_MAGIC];
+      oversized.extend(u64::MAX.to_le_bytes());
+      assert_eq!(
+        read_argument_frame(oversized.as_ptr(), "arg")
+          .unwrap_err()
+          .to_string(),
+        "Invalid argument 'arg': 18446744073709551615 bytes exceeds the maximum argument length of 268435456 bytes"
+      );
+
+      // the length prefix that preceded argument frames
+      let mut unframed = ((payload.len() + 8) as u64).to_ne_bytes().to_vec();
+      unframed.extend(&payload);
+      assert!(read_argument_frame(unframed.as_ptr(), "arg").is_err());
+      assert!(read_argument_frame(std::ptr::null(), "arg").is_err());
+    }
+  }
+
+  #[test]
+  fn test_last_error_is_cleared_once_read() {
+    ffi_helpers::update_last_error(crate::ArgumentError::new("arg", "failed"));
+    let length = membrane_last_error_length();
+    let mut buffer = vec![0 as std::os::raw::c_char; length as usize];
+
+    unsafe {
+      assert_eq!(
+        membrane_last_error_message(buffer.as_mut_ptr(), length - 1),
+        -1
+      );
+      assert_eq!(
+        membrane_last_error_message(buffer.as_mut_ptr(), length),
+        length
+      );
+      assert_eq!(
+        std::ffi::CStr::from_ptr(buffer.as_ptr()).to_str().unwrap(),
+        "Invalid argument 'arg': failed"
+      );
+    }
+    assert_eq!(membrane_last_error_length(), 0);
+  }
+
+  #[test]
+  fn test_frames_use_the_compiled_encoding() {
+    let value = (1u8, "ab".to_string());
+    assert_eq!(
+      encoding::serialize(Encoding::Bcs, &value).unwrap(),
+      vec![1, 2, b'a', b'b']
+    );
+    assert_eq!(
+      encoding::serialize(Encoding::Bincode, &value).unwrap(),
+      vec![1, 2, 0, 0, 0, 0, 0, 0, 0, b'a', b'b']
+    );
+
+    // BCS can't encode floats so Dart is sent a panic frame instead
+    let frame: (u8, String) = encoding::deserialize(
+      Encoding::Bcs,
+      &encoding::frame(Encoding::Bcs, &(1u8, 1.5f64)),
+    )
+    .unwrap();
+    assert_eq!(frame.0, crate::frame::PANIC);
+    assert!(frame.1.starts_with("the result couldn't be serialized"));
+  }
+
+  #[test]
+  fn test_cancel_all_tasks_in_a_namespace() {
+    let ns = std::ffi::CString::new("cancel_all").unwrap();
+    let other = std::ffi::CString::new("cancel_all_other").unwrap();
+    let abort = || futures::future::AbortHandle::new_pair().0;
+    register_task("cancel_all", TaskHandle::new(abort()));
+    register_task("cancel_all", TaskHandle::new(abort()));
+    let remaining = register_task("cancel_all_other", TaskHandle::new(abort()));
+
+    unsafe {
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 2);
+      assert_eq!(membrane_cancel_all_tasks(ns.as_ptr()), 2);
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 0);
+      assert_eq!(membrane_active_task_count(other.as_ptr()), 1);
+    }
+    assert_eq!(membrane_cancel_membrane_task(remaining), 1);
+  }
+
+  #[test]
+  fn test_a_closed_broadcast_is_forwarded_again() {
+    static BROADCAST: Broadcast<i32> = Broadcast::new();
+    let ns = std::ffi::CString::new("broadcast_closed").unwrap();
+    let forward = || {
+      let abort = futures::future::AbortHandle::new_pair().0;
+      register_task("broadcast_closed", TaskHandle::new(abort))
+    };
+    let mut first = BROADCAST.subscribe(true, forward);
+    let forwarder = BROADCAST.state().forwarder.unwrap();
+    BROADCAST.publish(1);
+    assert_eq!(first.next().now_or_never(), Some(Some(1)));
+
+    // what a canceled forwarder does before it stops
+    assert_eq!(membrane_cancel_membrane_task(forwarder), 1);
+    BROADCAST.close(forwarder);
+    assert_eq!(first.next().now_or_never(), Some(None));
+
+    let mut second = BROADCAST.subscribe(true, forward);
+    assert_eq!(second.next().now_or_never(), Some(Some(1)));
+    unsafe {
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 1);
+    }
+    drop((first, second));
+  }
+
+  #[test]
+  fn test_a_broadcast_without_subscribers_stops_forwarding() {
+    static BROADCAST: Broadcast<i32> = Broadcast::new();
+    let ns = std::ffi::CString::new("broadcast_unsubscribed").unwrap();
+    let forward = || {
+      let abort = futures::future::AbortHandle::new_pair().0;
+      register_task("broadcast_unsubscribed", TaskHandle::new(abort))
+    };
+    let first = BROADCAST.subscribe(false, forward);
+    let second = BROADCAST.subscribe(false, forward);
+
+    unsafe {
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 1);
+      drop(first);
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 1);
+      drop(second);
+      assert_eq!(membrane_active_task_count(ns.as_ptr()), 0);
+    }
   }
 }
@@ -2,7 +2,6 @@ mod test_utils;
 
 mod test {
   use super::test_utils::*;
-  use example;
   use membrane::Membrane;
   use serial_test::serial;
   use std::{fs::read_to_string, path::Path};
@@ -13,7 +12,7 @@ mod test {
     let path = Path::new("../dart_example");
 
     // reference the example lib so it doesn't get optimized away
-    let _ = example::load();
+    example::load();
 
     Membrane::new()
       .package_destination_dir(path)
@@ -26,6 +25,8 @@ mod test {
     let api = read_to_string(path.join("lib").join("accounts.dart")).unwrap();
     assert!(api.contains("@immutable\nclass AccountsApi {"));
     assert!(api.contains("Future<Contact> contact({required String userId}) async {"));
+    assert!(api.contains("Contact contactSync({required String userId}) {"));
+    assert!(api.contains("Future<Uint8List> reverseBytes({required Uint8List data}) async {"));
 
     let dart_type = read_to_string(
       path
@@ -59,16 +60,17 @@ class Contact {
 
     assert_contains_part(
       &headers,
-      "int32_t *membrane_accounts_contact(int64_t port, const char *user_id);",
+      "membrane_task_t membrane_accounts_contact(int64_t port, const char *user_id);",
     );
 
-    build_lib(&path.to_path_buf());
-    run_dart(&path.to_path_buf(), vec!["pub", "add", "test"], false);
-    run_dart(
-      &path.to_path_buf(),
-      vec!["test", "test/main_test.dart"],
-      true,
+    assert_contains_part(
+      &headers,
+      "uint8_t *membrane_accounts_contact_sync(const char *user_id);",
     );
+
+    build_lib(path);
+    run_dart(path, vec!["pub", "add", "test"], false);
+    run_dart(path, vec!["test", "test/main_test.dart"], true);
   }
 
   #[test]
@@ -77,7 +79,7 @@ class Contact {
     let path = Path::new("../dart_example");
 
     // reference the example lib so it doesn't get optimized away
-    let _ = example::load();
+    example::load();
 
     Membrane::new()
       .with_c_style_enums(false)
@@ -88,12 +90,70 @@ class Contact {
       .write_c_headers()
       .write_bindings();
 
-    build_lib(&path.to_path_buf());
-    run_dart(&path.to_path_buf(), vec!["pub", "add", "test"], false);
-    run_dart(
-      &path.to_path_buf(),
-      vec!["test", "test/enum_test.dart"],
-      true,
+    build_lib(path);
+    run_dart(path, vec!["pub", "add", "test"], false);
+    run_dart(path, vec!["test", "test/enum_test.dart"], true);
+  }
+
+  #[test]
+  fn schema_hash_is_exported_for_each_namespace() {
+    example::load();
+
+    let hash = |namespace: &str| {
+      let ns = std::ffi::CString::new(namespace).unwrap();
+      unsafe { membrane::membrane_schema_hash(ns.as_ptr()) }
+    };
+    assert_ne!(hash("accounts"), 0);
+    // a library built with `skip-generate` isn't checked
+    assert_ne!(hash("accounts"), u64::MAX);
+    assert_eq!(hash("accounts"), hash("accounts"));
+    assert_ne!(hash("accounts"), hash("locations"));
+    assert_eq!(hash("unknown"), 0);
+  }
+
+  #[test]
+  fn finished_tasks_are_forgotten() {
+    extern "C" {
+      fn membrane_accounts_slow_function(port: i64, sleep_for: i64) -> u64;
+    }
+    example::load();
+
+    let task = unsafe { membrane_accounts_slow_function(0, 1) };
+    assert!(forgotten_in_time(task));
+    assert_eq!(membrane::membrane_cancel_membrane_task(task), -1);
+  }
+
+  /// Polls until the task is no longer registered, granting no credits doesn't affect it
+  fn forgotten_in_time(task: u64) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while membrane::membrane_grant_credits(task, 0) != -1 {
+      if std::time::Instant::now() > deadline {
+        return false;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    true
+  }
+
+  #[test]
+  fn an_undecodable_stream_item_ends_the_task() {
+    extern "C" {
+      fn membrane_accounts_sum_stream(port: i64) -> u64;
+      fn membrane_push_accounts_sum_stream(task: u64, data: *const u8) -> i32;
+    }
+    example::load();
+
+    // a single byte can't be decoded as an `i64`
+    let mut frame = vec![membrane_types::ARG_FRAME_MAGIC];
+    frame.extend(1u64.to_le_bytes());
+    frame.push(1);
+
+    let task = unsafe { membrane_accounts_sum_stream(0) };
+    assert_eq!(
+      unsafe { membrane_push_accounts_sum_stream(task, frame.as_ptr()) },
+      1
     );
+    assert!(forgotten_in_time(task));
+    assert_eq!(membrane::membrane_cancel_membrane_task(task), -1);
   }
 }
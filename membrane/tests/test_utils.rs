@@ -2,7 +2,7 @@ use pretty_assertions::assert_eq;
 use std::fs;
 use std::io::Write;
 use std::process::{exit, Command};
-use std::{fmt, path::PathBuf};
+use std::{fmt, path::Path};
 
 pub fn assert_contains_part(left: &str, right: &str) {
   let left_no_ws = left.split_whitespace().collect::<String>();
@@ -27,7 +27,7 @@ impl<'a> fmt::Debug for PrettyString<'a> {
   }
 }
 
-pub fn build_lib(path: &PathBuf) {
+pub fn build_lib(path: &Path) {
   Command::new("cargo")
     .arg("build")
     .arg("-p")
@@ -46,11 +46,11 @@ pub fn build_lib(path: &PathBuf) {
   );
 }
 
-pub fn run_dart(path: &PathBuf, args: Vec<&str>, verbose: bool) {
+pub fn run_dart(path: &Path, args: Vec<&str>, verbose: bool) {
   let pub_get = Command::new("dart")
-      .current_dir(&path)
+      .current_dir(path)
       // set the library path to our temp pub project for linux
-      .env("LD_LIBRARY_PATH", &path)
+      .env("LD_LIBRARY_PATH", path)
       .arg("--disable-analytics")
       .args(args)
       .output()
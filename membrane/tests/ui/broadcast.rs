@@ -0,0 +1,35 @@
+use async_channel::{Receiver, Sender};
+use futures::Future;
+use membrane::async_dart;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+struct Runtime {}
+impl Runtime {
+  pub fn spawn<T>(&self, _future: T)
+  where
+    T: Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+  }
+}
+
+static RUNTIME: Runtime = Runtime {};
+
+#[derive(Serialize, Deserialize)]
+pub struct NotClone(i32);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cloneable(i32);
+
+#[async_dart(namespace = "a", broadcast = true)]
+pub static VALUE_IS_NOT_CLONE: Lazy<(Sender<NotClone>, Receiver<NotClone>)> =
+  Lazy::new(async_channel::unbounded);
+
+#[async_dart(namespace = "a", broadcast = true, replay = true)]
+pub static ERROR_IS_NOT_CLONE: Lazy<(
+  Sender<Result<Cloneable, NotClone>>,
+  Receiver<Result<Cloneable, NotClone>>,
+)> = Lazy::new(async_channel::unbounded);
+
+fn main() {}
@@ -0,0 +1,41 @@
+use futures::Future;
+use membrane::{async_dart, sync_dart, CancellationToken};
+
+struct Runtime {}
+impl Runtime {
+  pub fn spawn<T>(&self, _future: T)
+  where
+    T: Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+  }
+}
+
+static RUNTIME: Runtime = Runtime {};
+
+#[async_dart(namespace = "a", cancel_grace_ms = 100)]
+pub async fn cancellation_success(_token: CancellationToken) -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a")]
+pub fn sync_cancellation(_token: CancellationToken) -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", cancel_grace_ms = 100)]
+pub async fn grace_without_token() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", cancel_grace_ms = 0)]
+pub async fn zero_grace(_token: CancellationToken) -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", cancel_grace_ms = 100000000000000000000)]
+pub async fn overflowing_grace(_token: CancellationToken) -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
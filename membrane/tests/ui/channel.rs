@@ -0,0 +1,19 @@
+use async_channel::{Receiver, Sender};
+use membrane::async_dart;
+use once_cell::sync::Lazy;
+
+#[async_dart(namespace = "a", sender = true)]
+pub async fn not_a_channel() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", broadcast = true)]
+pub async fn not_a_broadcast_channel() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", replay = true)]
+pub static REPLAY_WITHOUT_BROADCAST: Lazy<(Sender<i32>, Receiver<i32>)> =
+  Lazy::new(async_channel::unbounded);
+
+fn main() {}
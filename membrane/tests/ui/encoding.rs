@@ -0,0 +1,36 @@
+use futures::Future;
+use membrane::{async_dart, sync_dart};
+
+struct Runtime {}
+impl Runtime {
+  pub fn spawn<T>(&self, _future: T)
+  where
+    T: Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+  }
+}
+
+static RUNTIME: Runtime = Runtime {};
+
+#[async_dart(namespace = "a", encoding = "bcs")]
+pub async fn bcs_success() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a", encoding = "bincode")]
+pub fn bincode_success() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", encoding = "json")]
+pub async fn json_encoding() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a", encoding = "BCS")]
+pub fn uppercase_encoding() -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
@@ -0,0 +1,39 @@
+use futures::{Future, Stream};
+use membrane::{async_dart, sync_dart, Progress};
+
+struct Runtime {}
+impl Runtime {
+  pub fn spawn<T>(&self, _future: T)
+  where
+    T: Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+  }
+}
+
+static RUNTIME: Runtime = Runtime {};
+
+#[async_dart(namespace = "a")]
+pub async fn progress_success(_progress: Progress<f64>) -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a")]
+pub fn sync_progress(_progress: Progress<f64>) -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a")]
+pub fn stream_progress(_progress: Progress<f64>) -> impl Stream<Item = Result<i32, String>> {
+  futures::stream::iter(vec![])
+}
+
+#[async_dart(namespace = "a")]
+pub async fn two_progress(
+  _first: Progress<f64>,
+  _second: Progress<i64>,
+) -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
@@ -0,0 +1,34 @@
+use membrane::async_dart;
+
+mod executor {
+  pub struct Executor {}
+  impl membrane::Runtime for Executor {
+    fn spawn<T>(&self, _future: T)
+    where
+      T: futures::Future + Send + 'static,
+      T::Output: Send + 'static,
+    {
+    }
+  }
+
+  pub static EXECUTOR: Executor = Executor {};
+}
+
+membrane::runtime!(executor::EXECUTOR);
+
+#[async_dart(namespace = "a")]
+pub async fn crate_runtime() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", runtime = "executor::EXECUTOR")]
+pub async fn custom_runtime() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", runtime = "executor::MISSING")]
+pub async fn missing_runtime() -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
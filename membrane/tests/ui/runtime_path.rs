@@ -0,0 +1,8 @@
+use membrane::async_dart;
+
+#[async_dart(namespace = "a", runtime = "not a path")]
+pub async fn invalid_runtime() -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
@@ -3,7 +3,7 @@ use membrane::async_dart;
 
 struct Runtime {}
 impl Runtime {
-  pub fn spawn<T>(&self, future: T)
+  pub fn spawn<T>(&self, _future: T)
   where
     T: Future + Send + 'static,
     T::Output: Send + 'static,
@@ -13,24 +13,51 @@ impl Runtime {
 
 static RUNTIME: Runtime = Runtime {};
 
+mod errors {
+  pub type Result<T> = std::result::Result<T, String>;
+}
+
 #[async_dart(namespace = "a")]
-pub async fn no_result() -> i32 {}
+pub async fn no_result() -> i32 {
+  10
+}
 
 #[async_dart(namespace = "a")]
-pub async fn no_result_bare_vec() -> Vec<i32> {}
+pub async fn no_result_bare_vec() -> Vec<i32> {
+  vec![]
+}
 
 #[async_dart(namespace = "a")]
-pub async fn bare_vec() -> Result<Vec<i32>, String> {}
+pub async fn no_error_type() -> Result<i32> {}
 
 #[async_dart(namespace = "a")]
-pub async fn bare_tuple() -> Result<(i32, i32), String> {}
+pub async fn bare_vec() -> Result<Vec<i32>, String> {
+  Ok(vec![])
+}
 
 #[async_dart(namespace = "a")]
-pub async fn option() -> Result<Option<i32>, String> {}
+pub async fn bare_tuple() -> Result<(i32, i32), String> {
+  Ok((1, 2))
+}
+
+#[async_dart(namespace = "a")]
+pub async fn option() -> Result<Option<i32>, String> {
+  Ok(None)
+}
 
 #[async_dart(namespace = "a")]
 pub async fn one_success() -> Result<i32, String> {
   Ok(10)
 }
 
+#[async_dart(namespace = "a")]
+pub async fn qualified_result() -> std::result::Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a")]
+pub async fn result_alias() -> errors::Result<i32> {
+  Ok(10)
+}
+
 fn main() {}
@@ -3,7 +3,7 @@ use membrane::async_dart;
 
 struct Runtime {}
 impl Runtime {
-  pub fn spawn<T>(&self, future: T)
+  pub fn spawn<T>(&self, _future: T)
   where
     T: Future + Send + 'static,
     T::Output: Send + 'static,
@@ -20,11 +20,51 @@ pub fn one_failure() -> impl Stream<i32, String> {}
 pub fn two_failure() -> impl Stream<Item = i32, String> {}
 
 #[async_dart(namespace = "a")]
-pub fn three_failure() -> impl Stream<Item = Result<Option<i32>, String>> {}
+pub fn option_success() -> impl Stream<Item = Result<Option<i32>, String>> {
+  futures::stream::iter(vec![])
+}
+
+#[async_dart(namespace = "a")]
+pub fn no_result_success() -> impl Stream<Item = i32> {
+  futures::stream::iter(vec![])
+}
+
+#[async_dart(namespace = "a")]
+pub fn input_stream_success(values: impl Stream<Item = i32>) -> impl Stream<Item = i32> {
+  values
+}
+
+#[async_dart(namespace = "a")]
+pub async fn two_input_streams(
+  _first: impl Stream<Item = i32>,
+  _second: impl Stream<Item = i32>,
+) -> i32 {
+  10
+}
 
 #[async_dart(namespace = "a")]
 pub fn one_success() -> impl Stream<Item = Result<i32, String>> {
   futures::stream::iter(vec![])
 }
 
+#[async_dart(namespace = "a", on_error = "terminate")]
+pub fn terminate_on_error_success() -> impl Stream<Item = Result<i32, String>> {
+  futures::stream::iter(vec![])
+}
+
+#[async_dart(namespace = "a", on_error = "terminate")]
+pub fn on_error_without_result() -> impl Stream<Item = i32> {
+  futures::stream::iter(vec![])
+}
+
+#[async_dart(namespace = "a", on_error = "continue")]
+pub async fn on_error_without_stream() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", on_error = "ignore")]
+pub fn unknown_on_error() -> impl Stream<Item = Result<i32, String>> {
+  futures::stream::iter(vec![])
+}
+
 fn main() {}
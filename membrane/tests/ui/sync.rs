@@ -0,0 +1,29 @@
+use futures::Stream;
+use membrane::sync_dart;
+
+#[sync_dart(namespace = "a")]
+pub async fn async_fn() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a")]
+pub fn stream() -> impl Stream<Item = Result<i32, String>> {
+  futures::stream::iter(vec![])
+}
+
+#[sync_dart(namespace = "a", timeout_ms = 100)]
+pub fn timeout() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[sync_dart(namespace = "a")]
+pub fn input_stream(_values: impl Stream<Item = i32>) -> i32 {
+  10
+}
+
+#[sync_dart(namespace = "a")]
+pub fn one_success() -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
@@ -0,0 +1,31 @@
+use futures::Future;
+use membrane::async_dart;
+
+struct Runtime {}
+impl Runtime {
+  pub fn spawn<T>(&self, _future: T)
+  where
+    T: Future + Send + 'static,
+    T::Output: Send + 'static,
+  {
+  }
+}
+
+static RUNTIME: Runtime = Runtime {};
+
+#[async_dart(namespace = "a", timeout_ms = 100)]
+pub async fn timeout_success() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", timeout_ms = 0)]
+pub async fn zero_timeout() -> Result<i32, String> {
+  Ok(10)
+}
+
+#[async_dart(namespace = "a", timeout_ms = 100000000000000000000)]
+pub async fn overflowing_timeout() -> Result<i32, String> {
+  Ok(10)
+}
+
+fn main() {}
@@ -1,14 +1,18 @@
 extern crate proc_macro;
 use membrane_types::c::CHeaderTypes;
-use membrane_types::dart::{DartArgs, DartParams, DartTransforms};
-use membrane_types::heck::MixedCase;
+use membrane_types::dart::{DartArgs, DartInputStream, DartParams, DartTransforms};
+use membrane_types::heck::{MixedCase, SnakeCase};
 use membrane_types::rust::{RustArgs, RustExternParams, RustTransforms};
-use membrane_types::{proc_macro2, quote, syn, Input, OutputStyle};
+use membrane_types::{
+  extract_type_from_progress, extract_type_from_stream, is_byte_buffer, is_cancellation_token,
+  proc_macro2, quote, syn, Input, OutputStyle,
+};
 use proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
-use quote::quote;
+use quote::{quote, quote_spanned};
 use syn::parse::{Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
 use syn::{
   parse_macro_input, AttributeArgs, Block, Expr, Ident, Lit, Meta, MetaNameValue, NestedMeta, Path,
   Token, Type,
@@ -20,6 +24,14 @@ mod parsers;
 struct Options {
   namespace: String,
   disable_logging: bool,
+  timeout: Option<syn::LitInt>,
+  runtime: Option<syn::LitStr>,
+  sender: bool,
+  broadcast: bool,
+  replay: bool,
+  on_error: Option<syn::LitStr>,
+  cancel_grace: Option<syn::LitInt>,
+  encoding: Option<syn::LitStr>,
 }
 
 fn extract_options(mut input: Vec<NestedMeta>, mut options: Options) -> Options {
@@ -40,8 +52,47 @@ fn extract_options(mut input: Vec<NestedMeta>, mut options: Options) -> Options
       options.disable_logging = val.value();
       options
     }
+    Some((ident, Lit::Bool(val))) if ident == "sender" => {
+      options.sender = val.value();
+      options
+    }
+    Some((ident, Lit::Bool(val))) if ident == "broadcast" => {
+      options.broadcast = val.value();
+      options
+    }
+    Some((ident, Lit::Bool(val))) if ident == "replay" => {
+      options.replay = val.value();
+      options
+    }
+    Some((ident, Lit::Str(val))) if ident == "on_error" => {
+      // checked in `to_token_stream` so that an invalid value is reported as a compile error
+      options.on_error = Some(val);
+      options
+    }
+    Some((ident, Lit::Str(val))) if ident == "encoding" => {
+      // checked in `to_token_stream` so that an invalid value is reported as a compile error
+      options.encoding = Some(val);
+      options
+    }
+    Some((ident, Lit::Str(val))) if ident == "runtime" => {
+      // checked in `to_token_stream` so that an invalid value is reported as a compile error
+      options.runtime = Some(val);
+      options
+    }
+    Some((ident, Lit::Int(val))) if ident == "timeout_ms" => {
+      // checked in `to_token_stream` so that an invalid value is reported as a compile error
+      options.timeout = Some(val);
+      options
+    }
+    Some((ident, Lit::Int(val))) if ident == "cancel_grace_ms" => {
+      // checked in `to_token_stream` so that an invalid value is reported as a compile error
+      options.cancel_grace = Some(val);
+      options
+    }
     Some(_) => {
-      panic!(r#"#[async_dart] only `namespace=""` and `disable_logging=true` are valid options"#);
+      panic!(
+        r#"#[async_dart] only `namespace=""`, `disable_logging=true`, `timeout_ms=1000`, `runtime=""`, `sender=true`, `broadcast=true`, `replay=true`, `on_error="terminate"`, `cancel_grace_ms=1000`, and `encoding="bcs"` are valid options"#
+      );
     }
     None => {
       // we've iterated over all options and didn't find a namespace (required)
@@ -59,16 +110,19 @@ fn extract_options(mut input: Vec<NestedMeta>, mut options: Options) -> Options
 #[derive(Debug)]
 struct ReprDart {
   fn_name: Ident,
+  is_async: bool,
   inputs: Vec<Input>,
   output_style: OutputStyle,
-  output: Expr,
-  error: Path,
+  output: Type,
+  error: Option<Path>,
 }
 
 impl Parse for ReprDart {
   fn parse(input: ParseStream) -> Result<Self> {
     let arg_buffer;
 
+    // parse and discard any other attributes so that we can get to the function
+    let _ = input.call(syn::Attribute::parse_outer)?;
     input.parse::<Token![pub]>()?;
 
     if input.peek(Token![static]) {
@@ -82,6 +136,7 @@ impl Parse for ReprDart {
       input.parse::<Token![;]>()?;
       return Ok(ReprDart {
         fn_name: ident,
+        is_async: false,
         inputs: Vec::new(),
         output_style,
         output: ret_type,
@@ -89,7 +144,8 @@ impl Parse for ReprDart {
       });
     }
 
-    if input.peek(Token![async]) {
+    let is_async = input.peek(Token![async]);
+    if is_async {
       input.parse::<Token![async]>()?;
     }
     input.parse::<Token![fn]>()?;
@@ -105,6 +161,7 @@ impl Parse for ReprDart {
 
     Ok(ReprDart {
       fn_name,
+      is_async,
       inputs: {
         let args: Punctuated<Expr, Token![,]> = arg_buffer.parse_terminated(Expr::parse)?;
         args
@@ -130,9 +187,32 @@ impl Parse for ReprDart {
 
 #[proc_macro_attribute]
 pub fn async_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
+  to_token_stream(attrs, input, false)
+}
+
+#[proc_macro_attribute]
+pub fn sync_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
+  to_token_stream(attrs, input, true)
+}
+
+/// Emits the annotated item unchanged along with a compile error in place of the generated code
+fn compile_error(mut functions: TokenStream, span: Span, message: &str) -> TokenStream {
+  functions.extend::<TokenStream>(syn::Error::new(span, message).to_compile_error().into());
+  functions
+}
+
+fn to_token_stream(attrs: TokenStream, input: TokenStream, is_sync: bool) -> TokenStream {
   let Options {
     namespace,
     disable_logging,
+    timeout,
+    runtime,
+    sender,
+    broadcast,
+    replay,
+    on_error,
+    cancel_grace,
+    encoding,
   } = extract_options(
     parse_macro_input!(attrs as AttributeArgs),
     Options::default(),
@@ -143,47 +223,341 @@ pub fn async_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
 
   let ReprDart {
     fn_name,
+    is_async,
     output_style,
     output,
     error,
     inputs,
-    ..
   } = parse_macro_input!(input as ReprDart);
 
-  let rust_outer_params: Vec<TokenStream2> = RustExternParams::from(&inputs).into();
-  let rust_transforms: Vec<TokenStream2> = RustTransforms::from(&inputs).into();
+  if is_sync && (is_async || output_style != OutputStyle::Serialized) {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[sync_dart] expects a synchronous function, use #[async_dart] for async functions and streams",
+    );
+  }
+
+  if is_sync && timeout.is_some() {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[sync_dart] does not support `timeout_ms`, use #[async_dart] for functions that need a deadline",
+    );
+  }
+
+  let timeout = match timeout.map(|val| (val.base10_parse::<u64>(), val)) {
+    // a deadline of zero would fail every call
+    Some((Ok(timeout), _)) if timeout > 0 => Some(timeout),
+    Some((_, val)) => {
+      return compile_error(
+        functions,
+        val.span(),
+        "#[async_dart] expects `timeout_ms` to be a positive integer",
+      )
+    }
+    None => None,
+  };
+
+  if sender && output_style != OutputStyle::Channel {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`sender = true` is only valid on a `Lazy<(Sender<T>, Receiver<T>)>` channel static",
+    );
+  }
+
+  if broadcast && output_style != OutputStyle::Channel {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`broadcast = true` is only valid on a `Lazy<(Sender<T>, Receiver<T>)>` channel static",
+    );
+  }
+
+  if replay && !broadcast {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`replay = true` requires `broadcast = true`",
+    );
+  }
+
+  let on_error = match on_error {
+    Some(val) if ["continue", "terminate"].contains(&val.value().as_str()) => Some(val.value()),
+    Some(val) => {
+      return compile_error(
+        functions,
+        val.span(),
+        r#"#[async_dart] expects `on_error` to be "continue" or "terminate""#,
+      )
+    }
+    None => None,
+  };
+
+  if on_error.is_some() && (output_style == OutputStyle::Serialized || error.is_none()) {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`on_error` is only valid on streams of `Result<T, E>`",
+    );
+  }
+
+  // the crate's `RUNTIME` is the default, `membrane::runtime!` points it at another executor
+  let runtime: Path = match runtime.map(|val| (val.parse(), val)) {
+    Some((Ok(runtime), _)) => runtime,
+    Some((Err(_), val)) => {
+      return compile_error(
+        functions,
+        val.span(),
+        "#[async_dart] expects `runtime` to be a path such as `crate::executors::BACKGROUND`",
+      )
+    }
+    None => syn::parse_quote!(crate::RUNTIME),
+  };
+
+  let encoding = match encoding_path(encoding) {
+    Ok(encoding) => encoding,
+    Err(error) => return compile_error(functions, error.span(), &error.to_string()),
+  };
+
+  let input_streams = inputs
+    .iter()
+    .filter(|input| extract_type_from_stream(&input.ty).is_some())
+    .count();
+
+  if input_streams > 0 && is_sync {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[sync_dart] does not support `impl Stream` arguments, use #[async_dart] instead",
+    );
+  }
+
+  if input_streams > 1 {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[async_dart] supports at most one `impl Stream` argument",
+    );
+  }
+
   let rust_inner_args: Vec<Ident> = RustArgs::from(&inputs).into();
 
+  // cancellation tokens are created on the Rust side so they never cross the FFI boundary
+  let (cancellation_tokens, inputs): (Vec<Input>, Vec<Input>) = inputs
+    .into_iter()
+    .partition(|input| is_cancellation_token(&input.rust_type));
+
+  if !cancellation_tokens.is_empty() && is_sync {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[sync_dart] does not support `CancellationToken` arguments, use #[async_dart] instead",
+    );
+  }
+
+  if cancel_grace.is_some() && cancellation_tokens.is_empty() {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`cancel_grace_ms` requires a `CancellationToken` argument",
+    );
+  }
+
+  let cancel_grace = match cancel_grace.map(|val| (val.base10_parse::<u64>(), val)) {
+    Some((Ok(grace), _)) if grace > 0 => Some(grace),
+    Some((_, val)) => {
+      return compile_error(
+        functions,
+        val.span(),
+        "#[async_dart] expects `cancel_grace_ms` to be a positive integer",
+      );
+    }
+    None => None,
+  };
+
+  // progress updates are posted to the same port as the result
+  let (progress, inputs): (Vec<Input>, Vec<Input>) = inputs
+    .into_iter()
+    .partition(|input| extract_type_from_progress(&input.ty).is_some());
+
+  if !progress.is_empty() && is_sync {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[sync_dart] does not support `Progress` arguments, use #[async_dart] instead",
+    );
+  }
+
+  if !progress.is_empty() && output_style != OutputStyle::Serialized {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "`Progress` is only supported by functions returning a single value, a stream can send progress as items",
+    );
+  }
+
+  if progress.len() > 1 {
+    return compile_error(
+      functions,
+      fn_name.span(),
+      "#[async_dart] supports at most one `Progress` argument",
+    );
+  }
+
+  let rust_outer_params: Vec<TokenStream2> = RustExternParams::from(&inputs).into();
+
   let c_header_types: Vec<String> = CHeaderTypes::from(&inputs).into();
 
   let dart_outer_params: Vec<String> = DartParams::from(&inputs).into();
   let dart_transforms: Vec<String> = DartTransforms::from(&inputs).into();
   let dart_inner_args: Vec<String> = DartArgs::from(&inputs).into();
+  let dart_input_stream: Option<String> = DartInputStream::from(&inputs).into();
 
-  let serializer = quote! {
-      match result {
-          Ok(value) => {
-              if let Ok(buffer) = ::membrane::bincode::serialize(&(true, value)) {
+  let is_bytes = is_byte_buffer(&type_name(&output));
+
+  let progress_setup = progress.iter().map(|Input { variable, .. }| {
+    let variable = Ident::new(variable, Span::call_site());
+    quote!(let #variable = ::membrane::Progress::new(_isolate, #is_bytes, MEMBRANE_ENCODING);)
+  });
+  let progress_type = progress
+    .first()
+    .and_then(|Input { ty, .. }| extract_type_from_progress(ty));
+
+  // errors are sent to Dart either way, "terminate" also stops reading from the stream
+  let on_error = match on_error.as_deref() {
+    Some("terminate") => quote!(break;),
+    _ => quote!(),
+  };
+
+  let (result_type, serializer, sync_serializer) = match &error {
+    // byte buffers are handed to Dart as-is, errors are wrapped in a list to tell them apart
+    Some(error) if is_bytes => (
+      quote!(::std::result::Result<#output, #error>),
+      quote! {
+          match result {
+              Ok(value) => {
+                  let buffer: ::std::vec::Vec<u8> = value.into();
                   _isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer));
               }
+              Err(err) => {
+                  let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &(::membrane::frame::ERR, err));
+                  _isolate.post(vec![::membrane::allo_isolate::ZeroCopyBuffer(buffer)]);
+                  #on_error
+              }
+          };
+      },
+      quote! {
+          match result {
+              Ok(value) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::OK, ::std::convert::Into::<::std::vec::Vec<u8>>::into(value)))),
+              Err(err) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::ERR, err))),
           }
-          Err(err) => {
-              if let Ok(buffer) = ::membrane::bincode::serialize(&(false, err)) {
+      },
+    ),
+    None if is_bytes => (
+      quote!(#output),
+      quote! {
+          let buffer: ::std::vec::Vec<u8> = result.into();
+          _isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer));
+      },
+      quote! {
+          error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::OK, ::std::convert::Into::<::std::vec::Vec<u8>>::into(result))))
+      },
+    ),
+    Some(error) => (
+      quote!(::std::result::Result<#output, #error>),
+      quote! {
+          match result {
+              Ok(value) => {
+                  let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &(::membrane::frame::OK, value));
                   _isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer));
               }
+              Err(err) => {
+                  let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &(::membrane::frame::ERR, err));
+                  _isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer));
+                  #on_error
+              }
+          };
+      },
+      quote! {
+          match result {
+              Ok(value) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::OK, value))),
+              Err(err) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::ERR, err))),
           }
-      };
+      },
+    ),
+    // without a `Result` every value is sent with the success tag
+    None => (
+      quote!(#output),
+      quote! {
+          let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &(::membrane::frame::OK, result));
+          _isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer));
+      },
+      quote! {
+          error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::OK, result)))
+      },
+    ),
+  };
+
+  let frame_post = if is_bytes {
+    quote!(_isolate.post(vec![::membrane::allo_isolate::ZeroCopyBuffer(buffer)]))
+  } else {
+    quote!(_isolate.post(::membrane::allo_isolate::ZeroCopyBuffer(buffer)))
+  };
+
+  // lets Dart close the stream once Rust has nothing more to send
+  let done_post = quote! {
+    let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &::membrane::frame::DONE);
+    #frame_post;
   };
 
+  // a stream ended by a panic or a timeout is done as well
+  let stream_done = if output_style == OutputStyle::Serialized {
+    quote!()
+  } else {
+    done_post.clone()
+  };
+
+  let broadcast_static = Ident::new(
+    format!("MEMBRANE_BROADCAST_{}", fn_name).as_str(),
+    Span::call_site(),
+  );
+
   let return_statement = match output_style {
+    // each subscription gets its own receiver which is fed by a single task draining the channel
+    OutputStyle::Channel if broadcast => {
+      quote! {
+        async move {
+          use ::membrane::futures::stream::StreamExt;
+          let mut receiver = membrane_broadcast_receiver;
+          loop {
+              membrane_flow_control.acquire().await;
+              let result: #result_type = match receiver.next().await {
+                  Some(result) => result,
+                  None => break,
+              };
+              membrane_flow_control.ready().await;
+              #serializer
+          }
+          #done_post
+        }
+      }
+    }
     OutputStyle::Channel => {
       quote! {
         async move {
           let receiver = #fn_name.1.clone();
-          while let Ok(result) = receiver.recv().await {
-              let result: ::std::result::Result<#output, #error> = result;
+          loop {
+              membrane_flow_control.acquire().await;
+              let result: #result_type = match receiver.recv().await {
+                  Ok(result) => result,
+                  Err(_) => break,
+              };
+              membrane_flow_control.ready().await;
               #serializer
           }
+          #done_post
         }
       }
     }
@@ -193,69 +567,369 @@ pub fn async_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
           use ::membrane::futures::stream::StreamExt;
           let mut stream = #fn_name(#(#rust_inner_args),*);
           ::membrane::futures::pin_mut!(stream);
-          while let Some(result) = stream.next().await {
-              let result: ::std::result::Result<#output, #error> = result;
+          loop {
+              membrane_flow_control.acquire().await;
+              let result: #result_type = match stream.next().await {
+                  Some(result) => result,
+                  None => break,
+              };
+              membrane_flow_control.ready().await;
               #serializer
           }
+          #done_post
         }
       }
     }
     OutputStyle::Serialized => quote! {
       async move {
-        let result: ::std::result::Result<#output, #error> = #fn_name(#(#rust_inner_args),*).await;
+        let result: #result_type = #fn_name(#(#rust_inner_args),*).await;
         #serializer
       }
     },
   };
 
+  // a panic would otherwise leave Dart waiting forever so it's sent as its own frame
+  let return_statement = quote! {
+    async move {
+      use ::membrane::futures::FutureExt;
+      if let Err(panic) = ::std::panic::AssertUnwindSafe(#return_statement).catch_unwind().await {
+        let message = ::membrane::panic_message(panic);
+        let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &(::membrane::frame::PANIC, message));
+        #frame_post;
+        #stream_done
+      }
+    }
+  };
+
+  // the task is dropped (and so aborted) once the deadline passes
+  let return_statement = match timeout {
+    Some(timeout) => quote! {
+      async move {
+        let task = #return_statement;
+        let timer = ::membrane::futures_timer::Delay::new(::std::time::Duration::from_millis(#timeout));
+        ::membrane::futures::pin_mut!(task);
+        if let ::membrane::futures::future::Either::Right(_) = ::membrane::futures::future::select(task, timer).await {
+          let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, &::membrane::frame::TIMEOUT);
+          #frame_post;
+          #stream_done
+        }
+      }
+    },
+    None => return_statement,
+  };
+
+  // once cancelled the function has the grace period to return before it's dropped
+  let (cancellation_setup, task_token, return_statement) = if cancellation_tokens.is_empty() {
+    (quote!(), quote!(None), return_statement)
+  } else {
+    let grace = cancel_grace.unwrap_or(1000);
+    let variables = cancellation_tokens
+      .iter()
+      .map(|Input { variable, .. }| Ident::new(variable, Span::call_site()));
+    (
+      quote! {
+          let membrane_cancellation_token = ::membrane::CancellationToken::new();
+          let membrane_task_token = membrane_cancellation_token.clone();
+          #(let #variables = membrane_cancellation_token.clone();)*
+      },
+      quote!(Some(membrane_task_token)),
+      quote! {
+        async move {
+          let task = #return_statement;
+          let grace = async move {
+            membrane_cancellation_token.cancelled().await;
+            ::membrane::futures_timer::Delay::new(::std::time::Duration::from_millis(#grace)).await;
+          };
+          ::membrane::futures::pin_mut!(task);
+          ::membrane::futures::pin_mut!(grace);
+          if let ::membrane::futures::future::Either::Right(_) = ::membrane::futures::future::select(task, grace).await {
+            #done_post
+          }
+        }
+      },
+    )
+  };
+
+  // streams are only polled for an item once Dart has granted a credit for it
+  let (flow_control_setup, task_flow_control) = if output_style == OutputStyle::Serialized {
+    (quote!(), quote!(None))
+  } else {
+    (
+      quote! {
+          let membrane_flow_control = ::std::sync::Arc::new(::membrane::FlowControl::default());
+          let membrane_task_flow_control = membrane_flow_control.clone();
+      },
+      quote!(Some(membrane_task_flow_control)),
+    )
+  };
+
+  // subscribe before returning to Dart so that values sent right after the call aren't missed
+  let broadcast_subscribe = if broadcast {
+    quote! {
+        // the forwarder is a task of its own in the namespace so that cancelling every task stops it,
+        // it's stopped as well once the last subscription is gone
+        let membrane_broadcast_receiver = #broadcast_static.subscribe(#replay, || {
+          let (membrane_forward_handle, membrane_forward_registration) = ::futures::future::AbortHandle::new_pair();
+          let membrane_forward_id = ::membrane::register_task(#namespace, ::membrane::TaskHandle::new(membrane_forward_handle));
+          #runtime.spawn(async move {
+            let _membrane_task = ::membrane::TaskGuard(membrane_forward_id);
+            let forward = async {
+              let receiver = #fn_name.1.clone();
+              while let Ok(result) = receiver.recv().await {
+                  #broadcast_static.publish(result);
+              }
+            };
+            let _ = ::futures::future::Abortable::new(forward, membrane_forward_registration).await;
+            // the next subscription starts a new forwarder
+            #broadcast_static.close(membrane_forward_id);
+          });
+          membrane_forward_id
+        });
+    }
+  } else {
+    quote!()
+  };
+
   let extern_c_fn_name = Ident::new(
     format!("membrane_{}_{}", namespace, fn_name).as_str(),
     Span::call_site(),
   );
 
-  let c_fn = quote! {
-      #[no_mangle]
-      #[allow(clippy::not_unsafe_ptr_arg_deref)]
-      pub extern "C" fn #extern_c_fn_name(_port: i64, #(#rust_outer_params),*) -> *const ::membrane::TaskHandle {
-          use crate::RUNTIME;
-          use ::membrane::{cstr, error, ffi_helpers};
-          use ::std::ffi::CStr;
+  let push_c_fn_name = Ident::new(
+    format!("membrane_push_{}_{}", namespace, fn_name).as_str(),
+    Span::call_site(),
+  );
+  // the sender created by the stream argument transform goes into the task handle for `push`
+  let stream_sender = if input_streams > 0 {
+    quote!(Some(membrane_stream_sender))
+  } else {
+    quote!(None)
+  };
 
-          let _isolate = ::membrane::allo_isolate::Isolate::new(_port);
-          let (membrane_future_handle, membrane_future_registration) = ::futures::future::AbortHandle::new_pair();
+  let argument_frame = quote! {
+    &(
+      ::membrane::frame::ARGUMENT,
+      membrane_argument_error.parameter,
+      membrane_argument_error.message,
+    )
+  };
+  let sync_arguments = transform_arguments(
+    &inputs,
+    quote!(return ::membrane::into_membrane_buffer(error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, #argument_frame)));),
+  );
+  // a pushed stream item that can't be decoded is reported like an invalid argument
+  let stream_rejection = if input_streams > 0 {
+    quote! {
+        let membrane_stream_rejected = {
+          let membrane_future_handle = membrane_future_handle.clone();
+          move |membrane_argument_error: ::membrane::ArgumentError| {
+            let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, #argument_frame);
+            #frame_post;
+            membrane_future_handle.abort();
+          }
+        };
+    }
+  } else {
+    quote!()
+  };
+  // the call is rejected without spawning a task, Dart's cleanup treats it as one that has finished
+  let async_arguments = transform_arguments(
+    &inputs,
+    quote! {
+      let buffer = ::membrane::encoding::frame(MEMBRANE_ENCODING, #argument_frame);
+      #frame_post;
+      #stream_done
+      return ::membrane::finished_task();
+    },
+  );
 
-          #(#rust_transforms)*
-          RUNTIME.spawn(
-            ::futures::future::Abortable::new(#return_statement, membrane_future_registration)
-          );
+  let c_fn = if is_sync {
+    quote! {
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref, clippy::too_many_arguments, clippy::useless_conversion)]
+        pub extern "C" fn #extern_c_fn_name(#(#rust_outer_params),*) -> *const u8 {
+            use ::membrane::error;
+            const MEMBRANE_ENCODING: ::membrane::Encoding = #encoding;
 
-          let handle = ::std::boxed::Box::new(::membrane::TaskHandle(membrane_future_handle));
-          ::std::boxed::Box::into_raw(handle)
-      }
+            #sync_arguments
+            let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(move || {
+              #fn_name(#(#rust_inner_args),*)
+            }));
+            let buffer = match result {
+              Ok(result) => {
+                let result: #result_type = result;
+                #sync_serializer
+              }
+              Err(panic) => {
+                let message = ::membrane::panic_message(panic);
+                error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::PANIC, message)))
+              }
+            };
+
+            ::membrane::into_membrane_buffer(buffer)
+        }
+    }
+  } else {
+    quote! {
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref, clippy::too_many_arguments, clippy::useless_conversion)]
+        pub extern "C" fn #extern_c_fn_name(_port: i64, #(#rust_outer_params),*) -> u64 {
+            #[allow(unused_imports)]
+            use ::membrane::Runtime as _;
+            use ::membrane::error;
+            const MEMBRANE_ENCODING: ::membrane::Encoding = #encoding;
+
+            let _isolate = ::membrane::allo_isolate::Isolate::new(_port);
+            let (membrane_future_handle, membrane_future_registration) = ::futures::future::AbortHandle::new_pair();
+
+            #stream_rejection
+            #async_arguments
+            #cancellation_setup
+            #(#progress_setup)*
+            #flow_control_setup
+            #broadcast_subscribe
+            // registered before it's spawned so that a task finishing right away is still forgotten
+            let membrane_task_id = ::membrane::register_task(#namespace, ::membrane::TaskHandle {
+              abort: membrane_future_handle,
+              sender: #stream_sender,
+              token: #task_token,
+              flow: #task_flow_control,
+            });
+            #runtime.spawn(async move {
+              let _membrane_task = ::membrane::TaskGuard(membrane_task_id);
+              let task = ::futures::future::Abortable::new(#return_statement, membrane_future_registration);
+              // Dart is still waiting on a task that's aborted before it returns
+              if task.await.is_err() {
+                #done_post
+              }
+            });
+
+            membrane_task_id
+        }
+    }
+  };
+
+  let push_fn = if input_streams > 0 {
+    quote! {
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn #push_c_fn_name(task: u64, data: *const u8) -> i32 {
+            unsafe { ::membrane::push_to_task(task, data) }
+        }
+    }
+  } else {
+    quote!()
+  };
+
+  let broadcast_static = if broadcast {
+    // checked one type at a time so the error points at the value or error type that isn't `Clone`
+    let assert_broadcast_value = std::iter::once(output.span())
+      .chain(error.as_ref().map(|error| error.span()))
+      .zip(std::iter::once(quote!(#output)).chain(error.as_ref().map(|error| quote!(#error))))
+      .map(|(span, ty)| quote_spanned!(span=> broadcast_value::<#ty>();));
+    quote! {
+        #[doc(hidden)]
+        static #broadcast_static: ::membrane::Broadcast<#result_type> = ::membrane::Broadcast::new();
+        const _: fn() = || {
+          fn broadcast_value<T: ::membrane::BroadcastValue>() {}
+          #(#assert_broadcast_value)*
+        };
+    }
+  } else {
+    quote!()
   };
 
   functions.extend::<TokenStream>(c_fn.into());
+  functions.extend::<TokenStream>(quote!(#push_fn).into());
+  functions.extend::<TokenStream>(broadcast_static.into());
+
+  if sender {
+    functions.extend::<TokenStream>(
+      channel_sender(
+        &namespace,
+        disable_logging,
+        &encoding,
+        &fn_name,
+        &output,
+        &error,
+      )
+      .into(),
+    );
+  }
 
   let c_name = extern_c_fn_name.to_string();
   let c_header_types = c_header_types.join(", ");
   let name = fn_name.to_string().to_mixed_case();
   let is_stream =
     output_style == OutputStyle::StreamSerialized || output_style == OutputStyle::Channel;
-  let return_type = match &output {
-    Expr::Tuple(_expr) => "()".to_string(),
-    Expr::Path(expr) => expr.path.segments.last().unwrap().ident.to_string(),
-    _ => unreachable!(),
+  let return_type = type_name(&output);
+  let error_type = match &error {
+    Some(error) => {
+      let error_type = error.segments.last().unwrap().ident.to_string();
+      quote!(Some(#error_type.to_string()))
+    }
+    None => quote!(None),
   };
-  let error_type = error.segments.last().unwrap().ident.to_string();
+  let timeout_ms = match timeout {
+    Some(timeout) => quote!(Some(#timeout)),
+    None => quote!(None),
+  };
+  let error_trace = error
+    .iter()
+    .map(|error| quote!(tracer.trace_type::<#error>(samples).unwrap();));
+  // byte buffers are never bincode encoded so they don't need to be traced
+  let output_trace = Some(&output)
+    .filter(|_| !is_bytes)
+    .map(|output| quote!(tracer.trace_type::<#output>(samples).unwrap();));
   let rust_arg_types = inputs
     .iter()
-    .map(|Input { ty, .. }| ty)
+    .filter(|Input { rust_type, .. }| {
+      let inner = rust_type
+        .strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+        .unwrap_or(rust_type);
+      !is_byte_buffer(inner)
+    })
+    .map(|Input { ty, .. }| extract_type_from_stream(ty).unwrap_or(ty))
+    .chain(progress_type)
     .collect::<Vec<&Type>>();
+  let progress_type_name = progress_type.map(type_name);
+  let progress_type = match &progress_type_name {
+    Some(progress_type) => quote!(Some(#progress_type.to_string())),
+    None => quote!(None),
+  };
 
+  let dart_input_stream = match dart_input_stream {
+    Some(listen) => quote!(Some(#listen.to_string())),
+    None => quote!(None),
+  };
   let dart_outer_params = dart_outer_params.join(", ");
   let dart_transforms = dart_transforms.join(";\n    ");
   let dart_inner_args = dart_inner_args.join(", ");
 
+  let trace = quote! {
+      |
+        tracer: &mut ::membrane::serde_reflection::Tracer,
+        samples: &mut ::membrane::serde_reflection::Samples
+      | {
+          #output_trace
+          #(#error_trace)*
+          // send all argument types over to serde-reflection, the primitives will be dropped
+          #(tracer.trace_type::<#rust_arg_types>(samples).unwrap();)*
+      }
+  };
+  // the Rust types as written since several of them can share a C type
+  let rust_types = inputs
+    .iter()
+    .map(|Input { rust_type, .. }| rust_type.as_str())
+    .chain(progress_type_name.as_deref())
+    .collect::<Vec<&str>>()
+    .join(", ");
+  let signature_output = match &error {
+    Some(error) => quote!(#output, #error).to_string(),
+    None => quote!(#output).to_string(),
+  };
+
   let _deferred_trace = quote! {
       ::membrane::inventory::submit! {
           #![crate = ::membrane]
@@ -265,25 +939,24 @@ pub fn async_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
                 extern_c_fn_types: #c_header_types.to_string(),
                 fn_name: #name.to_string(),
                 is_stream: #is_stream,
+                is_sync: #is_sync,
                 return_type: #return_type.to_string(),
-                error_type: #error_type.to_string(),
+                error_type: #error_type,
                 namespace: #namespace.to_string(),
                 disable_logging: #disable_logging,
+                timeout: #timeout_ms,
+                broadcast: #broadcast,
+                replay: #replay,
+                progress_type: #progress_type,
                 dart_outer_params: #dart_outer_params.to_string(),
                 dart_transforms: #dart_transforms.to_string(),
                 dart_inner_args: #dart_inner_args.to_string(),
+                dart_input_stream: #dart_input_stream,
                 output: "".to_string(),
+                encoding: #encoding,
               },
               namespace: #namespace.to_string(),
-              trace: |
-                tracer: &mut ::membrane::serde_reflection::Tracer,
-                samples: &mut ::membrane::serde_reflection::Samples
-              | {
-                  tracer.trace_type::<#output>(samples).unwrap();
-                  tracer.trace_type::<#error>(samples).unwrap();
-                  // send all argument types over to serde-reflection, the primitives will be dropped
-                  #(tracer.trace_type::<#rust_arg_types>(samples).unwrap();)*
-              }
+              trace: #trace
           }
       }
   };
@@ -295,9 +968,267 @@ pub fn async_dart(attrs: TokenStream, input: TokenStream) -> TokenStream {
   ))]
   functions.extend::<TokenStream>(_deferred_trace.into());
 
+  let fingerprint = membrane_types::fnv1a(
+    format!(
+      "{} {}({}) -> {} {} {}",
+      c_name, rust_types, c_header_types, output_style, signature_output, encoding
+    )
+    .as_bytes(),
+  );
+  let _deferred_schema = deferred_schema(&namespace, fingerprint, false, trace);
+
+  #[cfg(not(feature = "skip-generate"))]
+  functions.extend::<TokenStream>(_deferred_schema.into());
+
   functions
 }
 
+/// The part of a function or enum that goes into the schema hash of its namespace, unlike the
+/// trace used to generate Dart it's kept in release builds so that the library can be checked
+fn deferred_schema(
+  namespace: &str,
+  fingerprint: u64,
+  is_enum: bool,
+  trace: TokenStream2,
+) -> TokenStream2 {
+  quote! {
+      ::membrane::inventory::submit! {
+          #![crate = ::membrane]
+          ::membrane::DeferredSchema {
+              namespace: #namespace,
+              fingerprint: #fingerprint,
+              is_enum: #is_enum,
+              trace: #trace
+          }
+      }
+  }
+}
+
+/// A synchronous `send_<static>` function that lets Dart push values into a channel static
+fn channel_sender(
+  namespace: &str,
+  disable_logging: bool,
+  encoding: &TokenStream2,
+  static_name: &Ident,
+  output: &Type,
+  error: &Option<Path>,
+) -> TokenStream2 {
+  let fn_name = format!("send_{}", static_name.to_string().to_snake_case());
+  let inputs = vec![Input {
+    variable: "value".to_string(),
+    rust_type: quote!(#output).to_string().split_whitespace().collect(),
+    ty: output.clone(),
+  }];
+
+  let rust_outer_params: Vec<TokenStream2> = RustExternParams::from(&inputs).into();
+  let arguments = transform_arguments(
+    &inputs,
+    quote! {
+      return ::membrane::into_membrane_buffer(error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(
+        ::membrane::frame::ARGUMENT,
+        membrane_argument_error.parameter,
+        membrane_argument_error.message,
+      ))));
+    },
+  );
+  let c_header_types: Vec<String> = CHeaderTypes::from(&inputs).into();
+  let dart_outer_params: Vec<String> = DartParams::from(&inputs).into();
+  let dart_transforms: Vec<String> = DartTransforms::from(&inputs).into();
+  let dart_inner_args: Vec<String> = DartArgs::from(&inputs).into();
+
+  let value = match error {
+    Some(_) => quote!(Ok(value)),
+    None => quote!(value),
+  };
+
+  let extern_c_fn_name = Ident::new(
+    format!("membrane_{}_{}", namespace, fn_name).as_str(),
+    Span::call_site(),
+  );
+
+  let c_fn = quote! {
+      #[no_mangle]
+      #[allow(clippy::not_unsafe_ptr_arg_deref)]
+      pub extern "C" fn #extern_c_fn_name(#(#rust_outer_params),*) -> *const u8 {
+          use ::membrane::error;
+          const MEMBRANE_ENCODING: ::membrane::Encoding = #encoding;
+
+          #arguments
+          // sending fails if the channel is closed or full
+          let buffer = match #static_name.0.try_send(#value) {
+              Ok(()) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::OK, ()))),
+              Err(err) => error!(::membrane::encoding::serialize(MEMBRANE_ENCODING, &(::membrane::frame::ERR, err.to_string()))),
+          };
+
+          ::membrane::into_membrane_buffer(buffer)
+      }
+  };
+
+  let c_name = extern_c_fn_name.to_string();
+  let c_header_types = c_header_types.join(", ");
+  let name = fn_name.to_mixed_case();
+  let dart_outer_params = dart_outer_params.join(", ");
+  let dart_transforms = dart_transforms.join(";\n    ");
+  let dart_inner_args = dart_inner_args.join(", ");
+
+  let _deferred_trace = quote! {
+      ::membrane::inventory::submit! {
+          #![crate = ::membrane]
+          ::membrane::DeferredTrace {
+              function: ::membrane::Function {
+                extern_c_fn_name: #c_name.to_string(),
+                extern_c_fn_types: #c_header_types.to_string(),
+                fn_name: #name.to_string(),
+                is_stream: false,
+                is_sync: true,
+                return_type: "()".to_string(),
+                error_type: Some("String".to_string()),
+                namespace: #namespace.to_string(),
+                disable_logging: #disable_logging,
+                timeout: None,
+                broadcast: false,
+                replay: false,
+                progress_type: None,
+                dart_outer_params: #dart_outer_params.to_string(),
+                dart_transforms: #dart_transforms.to_string(),
+                dart_inner_args: #dart_inner_args.to_string(),
+                dart_input_stream: None,
+                output: "".to_string(),
+                encoding: #encoding,
+              },
+              namespace: #namespace.to_string(),
+              // the value type is already traced by the channel itself
+              trace: |
+                _tracer: &mut ::membrane::serde_reflection::Tracer,
+                _samples: &mut ::membrane::serde_reflection::Samples
+              | {}
+          }
+      }
+  };
+
+  let mut tokens = c_fn;
+
+  // by default only enable tracing in the dev profile or with an explicit flag
+  #[cfg(all(
+    any(debug_assertions, feature = "generate"),
+    not(feature = "skip-generate")
+  ))]
+  tokens.extend(_deferred_trace);
+
+  let fingerprint = membrane_types::fnv1a(
+    format!(
+      "{} {}({}) {}",
+      c_name, inputs[0].rust_type, c_header_types, encoding
+    )
+    .as_bytes(),
+  );
+  let _deferred_schema = deferred_schema(
+    namespace,
+    fingerprint,
+    false,
+    quote! {
+      |
+        _tracer: &mut ::membrane::serde_reflection::Tracer,
+        _samples: &mut ::membrane::serde_reflection::Samples
+      | {}
+    },
+  );
+
+  #[cfg(not(feature = "skip-generate"))]
+  tokens.extend(_deferred_schema);
+
+  tokens
+}
+
+/// The `membrane::Encoding` a function is compiled for, bincode unless `encoding` says otherwise
+fn encoding_path(encoding: Option<syn::LitStr>) -> Result<TokenStream2> {
+  let (value, span) = match encoding {
+    Some(encoding) => (encoding.value(), encoding.span()),
+    None => ("bincode".to_string(), Span::call_site()),
+  };
+
+  match value.as_str() {
+    "bincode" => Ok(quote!(::membrane::Encoding::Bincode)),
+    "bcs" => Ok(quote!(::membrane::Encoding::Bcs)),
+    _ => Err(syn::Error::new(
+      span,
+      format!(
+        r#"expects `encoding` to be "bincode" or "bcs", found "{}""#,
+        value
+      ),
+    )),
+  }
+}
+
+/// Converts the C arguments, binding them by name, or runs `rejected` with `membrane_argument_error`
+/// when one of them is invalid
+fn transform_arguments(inputs: &Vec<Input>, rejected: TokenStream2) -> TokenStream2 {
+  let rust_transforms: Vec<TokenStream2> = RustTransforms::from(inputs).into();
+  let mut bindings: Vec<Ident> = RustArgs::from(inputs).into();
+  // the sender half of a stream argument is kept in the task handle
+  if inputs
+    .iter()
+    .any(|input| extract_type_from_stream(&input.ty).is_some())
+  {
+    bindings.push(Ident::new("membrane_stream_sender", Span::call_site()));
+  }
+
+  quote! {
+    let membrane_arguments = (|| -> ::std::result::Result<_, ::membrane::ArgumentError> {
+      #(#rust_transforms)*
+      Ok((#(#bindings,)*))
+    })();
+    let (#(#bindings,)*) = match membrane_arguments {
+      Ok(arguments) => arguments,
+      Err(membrane_argument_error) => {
+        #rejected
+      }
+    };
+  }
+}
+
+/// A Rust type as a string with each path shortened to its last segment, e.g. `Vec<data::Contact>` becomes `Vec<Contact>`,
+/// except `bytes::Bytes` which keeps its crate so that it's still recognized as a byte buffer
+fn type_name(ty: &Type) -> String {
+  match ty {
+    Type::Tuple(tuple) => format!(
+      "({})",
+      tuple
+        .elems
+        .iter()
+        .map(type_name)
+        .collect::<Vec<String>>()
+        .join(",")
+    ),
+    Type::Path(type_path) if is_byte_buffer(&quote!(#type_path).to_string()) => quote!(#type_path)
+      .to_string()
+      .split_whitespace()
+      .collect::<String>()
+      .trim_start_matches("::")
+      .to_string(),
+    Type::Path(type_path) => {
+      let segment = type_path.path.segments.last().unwrap();
+      match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => format!(
+          "{}<{}>",
+          segment.ident,
+          args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+              syn::GenericArgument::Type(ty) => Some(type_name(ty)),
+              _ => None,
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+        ),
+        _ => segment.ident.to_string(),
+      }
+    }
+    _ => quote!(#ty).to_string().split_whitespace().collect(),
+  }
+}
+
 #[derive(Debug)]
 struct ReprDartEnum {
   name: Ident,
@@ -348,5 +1279,22 @@ pub fn dart_enum(attrs: TokenStream, input: TokenStream) -> TokenStream {
   ))]
   variants.extend::<TokenStream>(_deferred_trace.into());
 
+  let _deferred_schema = deferred_schema(
+    &namespace,
+    0,
+    true,
+    quote! {
+      |
+        tracer: &mut ::membrane::serde_reflection::Tracer,
+        _samples: &mut ::membrane::serde_reflection::Samples
+      | {
+          tracer.trace_simple_type::<#name>().unwrap();
+      }
+    },
+  );
+
+  #[cfg(not(feature = "skip-generate"))]
+  variants.extend::<TokenStream>(_deferred_schema.into());
+
   variants
 }
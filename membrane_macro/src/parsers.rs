@@ -1,8 +1,8 @@
 use membrane_types::{syn, OutputStyle};
 use syn::parse::{ParseStream, Result};
-use syn::{Error, Expr, Ident, Path, Token};
+use syn::{Error, Ident, Path, Token, Type};
 
-struct ReceiverReturnTypeParse((Expr, Path));
+struct ReceiverReturnTypeParse((Type, Option<Path>));
 
 impl syn::parse::Parse for ReceiverReturnTypeParse {
   fn parse(input: ParseStream) -> Result<Self> {
@@ -18,13 +18,18 @@ impl syn::parse::Parse for ReceiverReturnTypeParse {
       }
     }
     content.parse::<Token![<]>()?;
+    #[allow(clippy::redundant_closure)]
     let r = parse_type(&content).map(|r| ReceiverReturnTypeParse(r));
     content.parse::<Token![>]>()?;
+    // rustfmt adds a trailing comma when the tuple wraps
+    if content.peek(Token![,]) {
+      content.parse::<Token![,]>()?;
+    }
     r
   }
 }
 
-pub fn parse_channel_return_type(input: ParseStream) -> Result<(OutputStyle, Expr, Path)> {
+pub fn parse_channel_return_type(input: ParseStream) -> Result<(OutputStyle, Type, Option<Path>)> {
   let outer_span = input.span();
   match input.parse::<Ident>()? {
     ident if ident == "Lazy" => (),
@@ -41,10 +46,10 @@ pub fn parse_channel_return_type(input: ParseStream) -> Result<(OutputStyle, Exp
     let ReceiverReturnTypeParse((t, e)) = syn::parse2(group_tokens)?;
     return Ok((OutputStyle::Channel, t, e));
   }
-  return Err(Error::new(outer_span, "expected `(`"));
+  Err(Error::new(outer_span, "expected `(`"))
 }
 
-pub fn parse_stream_return_type(input: ParseStream) -> Result<(OutputStyle, Expr, Path)> {
+pub fn parse_stream_return_type(input: ParseStream) -> Result<(OutputStyle, Type, Option<Path>)> {
   input.parse::<Token![impl]>()?;
   let span = input.span();
   let stream_ident = input.parse::<Ident>()?;
@@ -52,7 +57,7 @@ pub fn parse_stream_return_type(input: ParseStream) -> Result<(OutputStyle, Expr
   let item_ident = input.parse::<Ident>()?;
 
   if stream_ident != "Stream" || item_ident != "Item" {
-    return Err(Error::new(span, "expected `impl Stream<Item = Result>`"));
+    return Err(Error::new(span, "expected `impl Stream<Item = T>`"));
   }
 
   input.parse::<Token![=]>()?;
@@ -62,57 +67,62 @@ pub fn parse_stream_return_type(input: ParseStream) -> Result<(OutputStyle, Expr
   Ok((OutputStyle::StreamSerialized, t, e))
 }
 
-pub fn parse_return_type(input: ParseStream) -> Result<(OutputStyle, Expr, Path)> {
+pub fn parse_return_type(input: ParseStream) -> Result<(OutputStyle, Type, Option<Path>)> {
   let (t, e) = parse_type(input)?;
   Ok((OutputStyle::Serialized, t, e))
 }
 
-fn parse_type(input: ParseStream) -> Result<(Expr, Path)> {
-  let outer_span = input.span();
-  match input.parse::<Ident>()? {
-    ident if ident == "Result" => (),
-    _ => {
-      return Err(Error::new(outer_span, "expected enum `Result`"));
+fn parse_type(input: ParseStream) -> Result<(Type, Option<Path>)> {
+  let span = input.span();
+  match input.fork().parse::<Type>() {
+    Ok(Type::Path(syn::TypePath { qself: None, path }))
+      if path.segments.len() > 1 && path.segments.last().unwrap().ident == "Result" =>
+    {
+      input.parse::<Type>()?;
+      return parse_qualified_result(&path, span);
     }
+    _ => (),
   }
+
+  // anything other than a `Result` is returned to Dart as an infallible value
+  if !matches!(input.fork().parse::<Ident>(), Ok(ident) if ident == "Result") {
+    return Ok((input.parse::<Type>()?, None));
+  }
+  input.parse::<Ident>()?;
   input.parse::<Token![<]>()?;
 
   let type_span = input.span();
-  // handle the empty unit () type
-  let t = if input.peek(syn::token::Paren) {
-    let tuple = input.parse::<syn::ExprTuple>()?;
-    if !tuple.elems.is_empty() {
-      return Err(Error::new(
-        type_span,
-        "A tuple may not be returned from an `async_dart` function. If a tuple is needed return a struct containing the tuple.",
-      ));
-    }
-    Expr::Tuple(tuple)
-  } else {
-    Expr::Path(input.parse::<syn::ExprPath>()?)
-  };
-
-  match input.parse::<Token![,]>() {
-    Ok(_) => (),
-    Err(_err) => {
-      let type_name = match t {
-        Expr::Path(syn::ExprPath { path, .. }) if !path.segments.is_empty() => {
-          path.segments.first().unwrap().ident.to_string()
-        }
-        _ => String::new(),
-      };
+  let t = input.parse::<Type>()?;
 
-      match type_name.as_str() {
-        "Vec" => {
-          return Err(Error::new(type_span, "A vector may not be returned from an `async_dart` function. If a vector is needed return a struct containing the vector."));
-        }
-        _ => {
-          return Err(Error::new(type_span, "expected a struct or scalar type"));
-        }
-      }
-    }
+  if input.parse::<Token![,]>().is_err() {
+    return Err(Error::new(type_span, "expected a struct or scalar type"));
   }
+
   let e = input.parse::<Path>()?;
   input.parse::<Token![>]>()?;
-  Ok((t, e))
+  Ok((t, Some(e)))
+}
+
+/// `std::result::Result<T, E>` is accepted like `Result<T, E>`, an alias that leaves out the error
+/// type (such as `anyhow::Result<T>`) isn't since Dart needs to know what the error is
+fn parse_qualified_result(
+  path: &Path,
+  span: crate::proc_macro2::Span,
+) -> Result<(Type, Option<Path>)> {
+  let args: Vec<&syn::GenericArgument> = match &path.segments.last().unwrap().arguments {
+    syn::PathArguments::AngleBracketed(args) => args.args.iter().collect(),
+    _ => vec![],
+  };
+
+  match args.as_slice() {
+    [syn::GenericArgument::Type(t), syn::GenericArgument::Type(Type::Path(e))]
+      if e.qself.is_none() =>
+    {
+      Ok((t.clone(), Some(e.path.clone())))
+    }
+    _ => Err(Error::new(
+      span,
+      "expected `Result<T, E>`, a `Result` alias without an explicit error type isn't supported",
+    )),
+  }
 }
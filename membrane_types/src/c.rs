@@ -1,3 +1,4 @@
+use crate::utils::extract_type_from_stream;
 use crate::Input;
 
 pub struct CHeaderTypes(Vec<String>);
@@ -6,7 +7,10 @@ impl From<&Vec<Input>> for CHeaderTypes {
   fn from(inputs: &Vec<Input>) -> Self {
     let mut stream = vec![];
 
-    for input in inputs {
+    for input in inputs
+      .iter()
+      .filter(|input| extract_type_from_stream(&input.ty).is_none())
+    {
       stream.push(format!(
         "{c_type}{variable}",
         c_type = c_type(&input.rust_type),
@@ -30,11 +34,27 @@ fn c_type(ty: &str) -> String {
     "i64" => "const signed long ",
     "f64" => "const double ",
     "bool" => "const uint8_t ",
+    "i8" => "const int8_t ",
+    "i16" => "const int16_t ",
+    "i32" => "const int32_t ",
+    "u8" => "const uint8_t ",
+    "u16" => "const uint16_t ",
+    "u32" => "const uint32_t ",
+    "u64" => "const uint64_t ",
+    "f32" => "const float ",
     serialized if !serialized.starts_with("Option<") => "const uint8_t *",
     "Option<String>" => "const char *",
     "Option<i64>" => "const signed long *",
     "Option<f64>" => "const double *",
     "Option<bool>" => "const uint8_t *",
+    "Option<i8>" => "const int8_t *",
+    "Option<i16>" => "const int16_t *",
+    "Option<i32>" => "const int32_t *",
+    "Option<u8>" => "const uint8_t *",
+    "Option<u16>" => "const uint16_t *",
+    "Option<u32>" => "const uint32_t *",
+    "Option<u64>" => "const uint64_t *",
+    "Option<f32>" => "const float *",
     serialized if serialized.starts_with("Option<") => "const uint8_t *",
     _ => unreachable!(),
   }
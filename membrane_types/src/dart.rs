@@ -1,10 +1,17 @@
-use crate::Input;
+use crate::utils::{
+  extract_type_from_option, extract_type_from_stream, extract_type_from_vec, extract_types_from_map,
+};
+use crate::{is_byte_buffer, Input};
 use heck::{CamelCase, MixedCase};
 use syn::Type;
 
+/// The largest tuple that may be returned to Dart, one `TupleN` class is generated for each size
+pub const MAX_TUPLE_SIZE: usize = 8;
+
 pub struct DartParams(Vec<String>);
 pub struct DartTransforms(Vec<String>);
 pub struct DartArgs(Vec<String>);
+pub struct DartInputStream(Option<String>);
 
 impl From<&Vec<Input>> for DartParams {
   fn from(inputs: &Vec<Input>) -> Self {
@@ -13,7 +20,7 @@ impl From<&Vec<Input>> for DartParams {
     for input in inputs {
       stream.push(format!(
         "{dart_type} {variable}",
-        dart_type = dart_type(&input.rust_type),
+        dart_type = dart_type(&input.rust_type, &input.ty),
         variable = &input.variable.to_mixed_case(),
       ))
     }
@@ -26,7 +33,10 @@ impl From<&Vec<Input>> for DartTransforms {
   fn from(inputs: &Vec<Input>) -> Self {
     let mut stream = vec![];
 
-    for input in inputs {
+    for input in inputs
+      .iter()
+      .filter(|input| extract_type_from_stream(&input.ty).is_none())
+    {
       stream.push(format!(
         "final c{variable} = {cast}",
         variable = &input.variable.to_camel_case(),
@@ -42,7 +52,10 @@ impl From<&Vec<Input>> for DartArgs {
   fn from(inputs: &Vec<Input>) -> Self {
     let mut stream = vec![];
 
-    for input in inputs {
+    for input in inputs
+      .iter()
+      .filter(|input| extract_type_from_stream(&input.ty).is_none())
+    {
       stream.push(format!(
         "c{variable}",
         variable = &input.variable.to_camel_case()
@@ -53,6 +66,25 @@ impl From<&Vec<Input>> for DartArgs {
   }
 }
 
+impl From<&Vec<Input>> for DartInputStream {
+  fn from(inputs: &Vec<Input>) -> Self {
+    // each item is serialized and handed to `_push`, which copies it over to Rust
+    Self(inputs.iter().find_map(|input| {
+      extract_type_from_stream(&input.ty).map(|item| {
+        format!(
+          r#"{variable}.listen((item) {{
+      final serializer = _Serializer();
+      {serialize}
+      _push(serializer.bytes);
+    }}, onDone: () => _push(null))"#,
+          variable = input.variable.to_mixed_case(),
+          serialize = serialize_value(item, "item", 0)
+        )
+      })
+    }))
+  }
+}
+
 impl From<DartParams> for Vec<String> {
   fn from(types: DartParams) -> Self {
     types.0
@@ -71,26 +103,114 @@ impl From<DartArgs> for Vec<String> {
   }
 }
 
-pub fn dart_fn_return_type(str_ty: &str) -> &str {
-  match str_ty {
-    "String" => "String",
-    "i32" => "int",
-    "i64" => "int",
-    "f32" => "double",
-    "f64" => "double",
-    "bool" => "bool",
-    "()" => "void",
-    _ => str_ty,
+impl From<DartInputStream> for Option<String> {
+  fn from(stream: DartInputStream) -> Self {
+    stream.0
+  }
+}
+
+pub fn dart_fn_return_type(str_ty: &str) -> String {
+  dart_bare_type(&parse_type(str_ty))
+}
+
+///
+/// Build the Dart expression that reads a value of the given Rust type from `deserializer`.
+/// Named types (structs and enums) are delegated to `named` since their deserialization
+/// depends on how the serde-generate classes were configured.
+pub fn dart_deserializer(str_ty: &str, named: &dyn Fn(&str) -> String) -> String {
+  deserialize_value(&parse_type(str_ty), named)
+}
+
+fn parse_type(str_ty: &str) -> Type {
+  syn::parse_str::<Type>(str_ty)
+    .unwrap_or_else(|_| panic!("{} could not be parsed as a Rust type", str_ty))
+}
+
+fn deserialize_value(ty: &Type, named: &dyn Fn(&str) -> String) -> String {
+  if is_bytes(ty) {
+    return "deserializer.deserializeUint8List()".to_string();
+  }
+
+  if let Some(inner) = extract_type_from_vec(ty) {
+    return format!(
+      "List<{dart_type}>.generate(deserializer.deserializeLength(), (_i) => {inner})",
+      dart_type = dart_bare_type(inner),
+      inner = deserialize_value(inner, named)
+    );
+  }
+
+  if let Some((key, value)) = extract_types_from_map(ty) {
+    return format!(
+      "Map<{key_type}, {value_type}>.fromEntries(List.generate(deserializer.deserializeLength(), (_i) => MapEntry({key}, {value})))",
+      key_type = dart_bare_type(key),
+      value_type = dart_bare_type(value),
+      key = deserialize_value(key, named),
+      value = deserialize_value(value, named)
+    );
+  }
+
+  if let Some(inner) = extract_type_from_option(ty) {
+    return format!(
+      "(deserializer.deserializeOptionTag() ? {} : null)",
+      deserialize_value(inner, named)
+    );
+  }
+
+  if let syn::Type::Tuple(tuple) = ty {
+    if tuple.elems.is_empty() {
+      return "null".to_string();
+    }
+
+    return format!(
+      "{dart_type}({items})",
+      dart_type = dart_bare_type(ty),
+      items = tuple
+        .elems
+        .iter()
+        .map(|elem| deserialize_value(elem, named))
+        .collect::<Vec<String>>()
+        .join(", ")
+    );
+  }
+
+  match type_ident(ty).as_str() {
+    "String" => "deserializer.deserializeString()".to_string(),
+    "bool" => "deserializer.deserializeBool()".to_string(),
+    "i8" => "deserializer.deserializeInt8()".to_string(),
+    "i16" => "deserializer.deserializeInt16()".to_string(),
+    "i32" => "deserializer.deserializeInt32()".to_string(),
+    "i64" => "deserializer.deserializeInt64()".to_string(),
+    "i128" => "deserializer.deserializeInt128()".to_string(),
+    "u8" => "deserializer.deserializeUint8()".to_string(),
+    "u16" => "deserializer.deserializeUint16()".to_string(),
+    "u32" => "deserializer.deserializeUint32()".to_string(),
+    "u64" => "deserializer.deserializeUint64()".to_string(),
+    "u128" => "deserializer.deserializeUint128()".to_string(),
+    "f32" => "deserializer.deserializeFloat32()".to_string(),
+    "f64" => "deserializer.deserializeFloat64()".to_string(),
+    ident => named(ident),
   }
 }
 
-fn dart_type(str_ty: &str) -> String {
+fn dart_type(str_ty: &str, ty: &Type) -> String {
+  if let Some(item) = extract_type_from_stream(ty) {
+    return format!("required Stream<{}>", dart_bare_type(item));
+  }
+
   let ser_type;
   match str_ty {
     "String" => "required String",
     "i64" => "required int",
     "f64" => "required double",
     "bool" => "required bool",
+    "i8" | "i16" | "i32" | "i128" | "u8" | "u16" | "u32" | "u64" | "u128" | "f32" => {
+      ser_type = format!("required {}", dart_bare_type(ty));
+      &ser_type
+    }
+    _ if is_bytes(ty) || extract_type_from_vec(ty).is_some() => {
+      ser_type = format!("required {}", dart_bare_type(ty));
+      &ser_type
+    }
     serialized if !serialized.starts_with("Option<") => {
       ser_type = format!("required {} ", str_ty.split("::").last().unwrap().trim());
       &ser_type
@@ -99,6 +219,18 @@ fn dart_type(str_ty: &str) -> String {
     "Option<i64>" => "int?",
     "Option<f64>" => "double?",
     "Option<bool>" => "bool?",
+    "Option<i8>" | "Option<i16>" | "Option<i32>" | "Option<i128>" | "Option<u8>"
+    | "Option<u16>" | "Option<u32>" | "Option<u64>" | "Option<u128>" | "Option<f32>" => {
+      ser_type = dart_bare_type(ty);
+      &ser_type
+    }
+    _ if extract_type_from_option(ty)
+      .filter(|inner| is_bytes(inner) || extract_type_from_vec(inner).is_some())
+      .is_some() =>
+    {
+      ser_type = dart_bare_type(ty);
+      &ser_type
+    }
     serialized if serialized.starts_with("Option<") => {
       ser_type = format!(
         "{}? ",
@@ -106,7 +238,7 @@ fn dart_type(str_ty: &str) -> String {
           .split("::")
           .last()
           .unwrap()
-          .trim_end_matches(|c| c == ' ' || c == '>')
+          .trim_end_matches([' ', '>'])
       );
       &ser_type
     }
@@ -118,31 +250,16 @@ fn dart_type(str_ty: &str) -> String {
 fn cast_dart_type_to_c(str_ty: &str, variable: &str, ty: &Type) -> String {
   match ty {
     &syn::Type::Reference(_) => panic!("{}", unsupported_type_error(str_ty, variable, "a struct")),
-    &syn::Type::Tuple(_) | &syn::Type::Slice(_) | &syn::Type::Array(_) => {
-      panic!("{}", unsupported_type_error(str_ty, variable, "a struct"))
-    }
-    &syn::Type::Path(ref p) => match p.path.segments.first() {
-      Some(segment) if segment.ident == "Vec" => {
-        panic!("{}", unsupported_type_error(str_ty, variable, "a struct"))
-      }
-      _ => (),
-    },
+    &syn::Type::Tuple(_) => panic!("{}", unsupported_type_error(str_ty, variable, "a struct")),
+    &syn::Type::Slice(_) | &syn::Type::Array(_) => {
+      panic!("{}", unsupported_type_error(str_ty, variable, "a Vec"))
+    }
     _ => (),
   };
 
   match str_ty {
     "&str" => panic!("{}", unsupported_type_error(str_ty, variable, "String")),
     "char" => panic!("{}", unsupported_type_error(str_ty, variable, "String")),
-    "i8" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "i16" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "i32" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "i128" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "u8" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "u16" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "u32" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "u64" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "u128" => panic!("{}", unsupported_type_error(str_ty, variable, "i64")),
-    "f32" => panic!("{}", unsupported_type_error(str_ty, variable, "f64")),
     //
     // supported types
     //
@@ -159,12 +276,34 @@ fn cast_dart_type_to_c(str_ty: &str, variable: &str, ty: &Type) -> String {
     "bool" => format!("{variable} ? 1 : 0", variable = variable.to_mixed_case()),
     "i64" => variable.to_mixed_case(),
     "f64" => variable.to_mixed_case(),
+    narrow if narrow_scalar(narrow).is_some() => format!(
+      r#"(){{
+      {range_check}
+      return {variable};
+    }}()"#,
+      range_check = range_check(narrow, &variable.to_mixed_case()),
+      variable = variable.to_mixed_case()
+    ),
+    "u64" => format!(
+      r#"(){{
+      {range_check}
+      return {variable}.toBigInt().toSigned(64).toInt();
+    }}()"#,
+      range_check = range_check(str_ty, &variable.to_mixed_case()),
+      variable = variable.to_mixed_case()
+    ),
+    bytes if is_byte_buffer(bytes) => format!(
+      r#"(){{
+      {copy}
+    }}()"#,
+      copy = copy_bytes_partial(&variable.to_mixed_case()),
+    ),
     serialized if !serialized.starts_with("Option<") => format!(
       r#"(){{
-      final data = {variable}.bincodeSerialize();
+      {encode}
       {serialize}
     }}()"#,
-      variable = variable.to_mixed_case(),
+      encode = encode(ty, &variable.to_mixed_case()),
       serialize = serialization_partial(),
     ),
     "Option<String>" => {
@@ -216,21 +355,247 @@ fn cast_dart_type_to_c(str_ty: &str, variable: &str, ty: &Type) -> String {
     }}()"#,
       variable = variable.to_mixed_case()
     ),
+    narrow
+      if narrow.starts_with("Option<")
+        && (narrow_scalar(&narrow[7..narrow.len() - 1]).is_some() || narrow == "Option<u64>") =>
+    {
+      let inner = &narrow[7..narrow.len() - 1];
+      let (native_type, value) = match narrow_scalar(inner) {
+        Some((native_type, ..)) => (native_type, variable.to_mixed_case()),
+        None => (
+          "Uint64",
+          format!(
+            "{}.toBigInt().toSigned(64).toInt()",
+            variable.to_mixed_case()
+          ),
+        ),
+      };
+
+      format!(
+        r#"(){{
+      if ({variable} == null) {{
+        return nullptr;
+      }}
+      {range_check}
+      final ptr = calloc<{native_type}>();
+      _toFree.add(ptr);
+      ptr.asTypedList(1).setAll(0, [{value}]);
+      return ptr;
+    }}()"#,
+        variable = variable.to_mixed_case(),
+        range_check = range_check(inner, &variable.to_mixed_case()),
+        native_type = native_type,
+        value = value
+      )
+    }
+    _ if extract_type_from_option(ty)
+      .filter(|inner| is_bytes(inner))
+      .is_some() =>
+    {
+      format!(
+        r#"(){{
+      if ({variable} == null) {{
+        return nullptr;
+      }}
+      {copy}
+    }}()"#,
+        variable = variable.to_mixed_case(),
+        copy = copy_bytes_partial(&variable.to_mixed_case()),
+      )
+    }
     serialized if serialized.starts_with("Option<") => format!(
       r#"(){{
       if ({variable} == null) {{
         return nullptr;
       }}
-      final data = {variable}.bincodeSerialize();
+      {encode}
       {serialize}
     }}()"#,
       variable = variable.to_mixed_case(),
+      encode = encode(
+        extract_type_from_option(ty).unwrap(),
+        &variable.to_mixed_case()
+      ),
       serialize = serialization_partial(),
     ),
     _ => unreachable!(),
   }
 }
 
+/// `_Serializer` is aliased to the serializer of the package's encoding
+fn encode(ty: &Type, variable: &str) -> String {
+  format!(
+    r#"final serializer = _Serializer();
+      {serialize}
+      final data = serializer.bytes;"#,
+    serialize = serialize_value(ty, variable, 0)
+  )
+}
+
+/// The Dart FFI type and the valid range of each scalar that is narrower than the Dart `int` or `double`
+fn narrow_scalar(str_ty: &str) -> Option<(&'static str, &'static str, &'static str)> {
+  match str_ty {
+    "i8" => Some(("Int8", "-128", "127")),
+    "i16" => Some(("Int16", "-32768", "32767")),
+    "i32" => Some(("Int32", "-2147483648", "2147483647")),
+    "u8" => Some(("Uint8", "0", "255")),
+    "u16" => Some(("Uint16", "0", "65535")),
+    "u32" => Some(("Uint32", "0", "4294967295")),
+    "f32" => Some(("Float", "-3.4028234663852886e+38", "3.4028234663852886e+38")),
+    _ => None,
+  }
+}
+
+/// Throw before crossing the FFI boundary if the value would be truncated by the Rust type
+fn range_check(str_ty: &str, variable: &str) -> String {
+  let condition = match (str_ty, narrow_scalar(str_ty)) {
+    ("u64", _) => format!(
+      "{variable}.toBigInt().isNegative || {variable}.toBigInt().bitLength > 64",
+      variable = variable
+    ),
+    // infinity and NaN are valid f32 values
+    ("f32", Some((_, min, max))) => format!(
+      "{variable}.isFinite && ({variable} < {min} || {variable} > {max})",
+      variable = variable,
+      min = min,
+      max = max
+    ),
+    (_, Some((_, min, max))) => format!(
+      "{variable} < {min} || {variable} > {max}",
+      variable = variable,
+      min = min,
+      max = max
+    ),
+    _ => unreachable!(),
+  };
+
+  format!(
+    r#"if ({condition}) {{
+        throw ArgumentError.value({variable}, '{variable}', 'is out of range for the Rust type {str_ty}');
+      }}"#,
+    condition = condition,
+    variable = variable,
+    str_ty = str_ty
+  )
+}
+
+fn serialize_value(ty: &Type, value: &str, depth: usize) -> String {
+  if let Some(inner) = extract_type_from_vec(ty) {
+    return format!(
+      r#"serializer.serializeLength({value}.length);
+        for (final item{depth} in {value}) {{
+          {inner}
+        }}"#,
+      value = value,
+      depth = depth,
+      inner = serialize_value(inner, &format!("item{}", depth), depth + 1)
+    );
+  }
+
+  if let Some(inner) = extract_type_from_option(ty) {
+    return format!(
+      r#"if ({value} == null) {{
+          serializer.serializeOptionTag(false);
+        }} else {{
+          serializer.serializeOptionTag(true);
+          {inner}
+        }}"#,
+      value = value,
+      inner = serialize_value(inner, value, depth)
+    );
+  }
+
+  let method = match type_ident(ty).as_str() {
+    "String" => "serializeString",
+    "bool" => "serializeBool",
+    "i8" => "serializeInt8",
+    "i16" => "serializeInt16",
+    "i32" => "serializeInt32",
+    "i64" => "serializeInt64",
+    "i128" => "serializeInt128",
+    "u8" => "serializeUint8",
+    "u16" => "serializeUint16",
+    "u32" => "serializeUint32",
+    "u64" => "serializeUint64",
+    "u128" => "serializeUint128",
+    "f32" => "serializeFloat32",
+    "f64" => "serializeFloat64",
+    _ => return format!("{}.serialize(serializer);", value),
+  };
+
+  format!("serializer.{}({});", method, value)
+}
+
+fn dart_bare_type(ty: &Type) -> String {
+  if is_bytes(ty) {
+    return "Uint8List".to_string();
+  }
+
+  if let Some(inner) = extract_type_from_vec(ty) {
+    return format!("List<{}>", dart_bare_type(inner));
+  }
+
+  if let Some((key, value)) = extract_types_from_map(ty) {
+    return format!("Map<{}, {}>", dart_bare_type(key), dart_bare_type(value));
+  }
+
+  if let Some(inner) = extract_type_from_option(ty) {
+    return format!("{}?", dart_bare_type(inner));
+  }
+
+  if let syn::Type::Tuple(tuple) = ty {
+    if tuple.elems.is_empty() {
+      return "void".to_string();
+    }
+
+    if tuple.elems.len() > MAX_TUPLE_SIZE {
+      panic!(
+        "Tuples with more than {} elements are not supported, please use a struct instead",
+        MAX_TUPLE_SIZE
+      );
+    }
+
+    return format!(
+      "Tuple{}<{}>",
+      tuple.elems.len(),
+      tuple
+        .elems
+        .iter()
+        .map(dart_bare_type)
+        .collect::<Vec<String>>()
+        .join(", ")
+    );
+  }
+
+  match type_ident(ty).as_str() {
+    "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" => "int".to_string(),
+    "u64" => "Uint64".to_string(),
+    "i128" => "Int128".to_string(),
+    "u128" => "Uint128".to_string(),
+    "f32" | "f64" => "double".to_string(),
+    ident => ident.to_string(),
+  }
+}
+
+fn is_bytes(ty: &Type) -> bool {
+  is_byte_buffer(
+    &quote::quote!(#ty)
+      .to_string()
+      .split_whitespace()
+      .collect::<String>(),
+  )
+}
+
+fn type_ident(ty: &Type) -> String {
+  match ty {
+    syn::Type::Path(p) => p.path.segments.last().unwrap().ident.to_string(),
+    _ => panic!(
+      "A Rust type of {} is not supported inside of a Vec, Map, or tuple",
+      quote::quote!(#ty)
+    ),
+  }
+}
+
 fn unsupported_type_error(ty: &str, variable: &str, new_ty: &str) -> String {
   format!(
     "A Rust type of {ty} is invalid for `{var}: {ty}`. Please use {new_ty} instead.",
@@ -240,13 +605,34 @@ fn unsupported_type_error(ty: &str, variable: &str, new_ty: &str) -> String {
   )
 }
 
-fn serialization_partial() -> &'static str {
-  r#"final ptr = calloc<Uint8>(data.length + 8);
-_toFree.add(ptr);
-final blobBytes = ptr.asTypedList(data.length + 8);
-final payloadLength = Int64List(1);
-payloadLength.setAll(0, [data.length + 8]);
-blobBytes.setAll(0, payloadLength);
-blobBytes.setAll(8, data);
-return ptr;"#
+fn serialization_partial() -> String {
+  format!(
+    r#"{frame}
+      return ptr;"#,
+    frame = frame_partial("data")
+  )
+}
+
+/// Byte buffers are copied into native memory with a single `setAll` instead of being encoded
+fn copy_bytes_partial(variable: &str) -> String {
+  format!(
+    r#"{frame}
+      return ptr;"#,
+    frame = frame_partial(variable)
+  )
+}
+
+/// Copies `data` into a `ptr` holding the magic byte, the little endian u64 length and the payload
+pub fn frame_partial(data: &str) -> String {
+  format!(
+    r#"final ptr = calloc<Uint8>({data}.length + {header});
+      _toFree.add(ptr);
+      final frame = ptr.asTypedList({data}.length + {header});
+      frame[0] = {magic:#04x};
+      ByteData.sublistView(frame).setUint64(1, {data}.length, Endian.little);
+      frame.setAll({header}, {data});"#,
+    data = data,
+    header = crate::ARG_FRAME_HEADER_LENGTH,
+    magic = crate::ARG_FRAME_MAGIC
+  )
 }
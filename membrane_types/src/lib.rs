@@ -10,6 +10,13 @@ pub mod dart;
 pub mod rust;
 mod utils;
 
+pub use utils::{extract_type_from_progress, extract_type_from_stream};
+
+/// Leads every framed argument that Dart passes to Rust, it changes whenever the frame layout does
+pub const ARG_FRAME_MAGIC: u8 = 0xb1;
+/// The magic byte followed by the little endian u64 length of the payload
+pub const ARG_FRAME_HEADER_LENGTH: usize = 9;
+
 #[derive(Debug)]
 pub struct Input {
   pub variable: String,
@@ -24,6 +31,29 @@ pub enum OutputStyle {
   Serialized,
 }
 
+/// Byte buffers (`Vec<u8>` and `bytes::Bytes`) skip bincode and cross the FFI boundary as raw bytes,
+/// `Bytes` needs its crate in the path so that another type with the same name isn't mistaken for it
+pub fn is_byte_buffer(str_ty: &str) -> bool {
+  let ty = str_ty.split_whitespace().collect::<String>();
+  matches!(
+    ty.trim_start_matches("::"),
+    "Vec<u8>" | "std::vec::Vec<u8>" | "bytes::Bytes"
+  )
+}
+
+/// FNV-1a, unlike `DefaultHasher` its output is stable across Rust versions so a hash computed by the
+/// macro can be compared with one computed by the generator
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+/// A `membrane::CancellationToken` parameter is created by Membrane rather than passed in from Dart
+pub fn is_cancellation_token(str_ty: &str) -> bool {
+  str_ty.rsplit("::").next() == Some("CancellationToken")
+}
+
 impl fmt::Display for OutputStyle {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "{:?}", self)
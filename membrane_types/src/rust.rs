@@ -1,11 +1,14 @@
-use crate::utils::extract_type_from_option;
-use crate::Input;
+use crate::utils::{extract_type_from_option, extract_type_from_stream};
+use crate::{is_byte_buffer, Input};
 
+use heck::MixedCase;
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::quote as q;
 use syn::{Ident, Type};
 
 pub struct RustExternParams(Vec<TokenStream2>);
+/// Statements converting the C arguments to Rust, they return early with `?` so they belong in a
+/// closure returning `Result<_, membrane::ArgumentError>` with a `MEMBRANE_ENCODING` const in scope
 pub struct RustTransforms(Vec<TokenStream2>);
 pub struct RustArgs(Vec<Ident>);
 
@@ -13,7 +16,11 @@ impl From<&Vec<Input>> for RustExternParams {
   fn from(inputs: &Vec<Input>) -> Self {
     let mut stream = vec![];
 
-    for input in inputs {
+    // stream items are pushed in later so they don't have a parameter
+    for input in inputs
+      .iter()
+      .filter(|input| extract_type_from_stream(&input.ty).is_none())
+    {
       let variable = Ident::new(&input.variable, Span::call_site());
       let c_type = rust_c_type(&input.rust_type);
       stream.push(q!(#variable: #c_type))
@@ -29,6 +36,11 @@ impl From<&Vec<Input>> for RustTransforms {
 
     for input in inputs {
       let variable = Ident::new(&input.variable, Span::call_site());
+      if let Some(item) = extract_type_from_stream(&input.ty) {
+        stream.push(receive_stream(variable, &input.variable, item));
+        continue;
+      }
+
       let cast = cast_c_type_to_rust(&input.rust_type, &input.variable, &input.ty);
       stream.push(q!(let #variable = #cast;))
     }
@@ -73,11 +85,27 @@ fn rust_c_type(ty: &str) -> TokenStream2 {
     "i64" => q!(::std::os::raw::c_long),
     "f64" => q!(::std::os::raw::c_double),
     "bool" => q!(::std::os::raw::c_char), // i8
+    "i8" => q!(i8),
+    "i16" => q!(i16),
+    "i32" => q!(i32),
+    "u8" => q!(u8),
+    "u16" => q!(u16),
+    "u32" => q!(u32),
+    "u64" => q!(u64),
+    "f32" => q!(f32),
     serialized if !serialized.starts_with("Option<") => q!(*const u8),
     "Option<String>" => q!(*const ::std::os::raw::c_char),
     "Option<i64>" => q!(*const ::std::os::raw::c_long),
     "Option<f64>" => q!(*const ::std::os::raw::c_double),
     "Option<bool>" => q!(*const ::std::os::raw::c_char), // i8
+    "Option<i8>" => q!(*const i8),
+    "Option<i16>" => q!(*const i16),
+    "Option<i32>" => q!(*const i32),
+    "Option<u8>" => q!(*const u8),
+    "Option<u16>" => q!(*const u16),
+    "Option<u32>" => q!(*const u32),
+    "Option<u64>" => q!(*const u64),
+    "Option<f32>" => q!(*const f32),
     serialized if serialized.starts_with("Option<") => q!(*const u8),
     _ => unreachable!(),
   }
@@ -86,14 +114,15 @@ fn rust_c_type(ty: &str) -> TokenStream2 {
 fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2 {
   match str_ty {
     "String" => {
+      let parameter = variable.to_mixed_case();
       let variable = Ident::new(variable, Span::call_site());
-      q!(cstr!(#variable).to_string())
+      q!(unsafe { ::membrane::read_argument_string(#variable, #parameter) }?)
     }
     "i64" => {
       let variable = Ident::new(variable, Span::call_site());
       q!(#variable)
     }
-    "f64" => {
+    "f64" | "i8" | "i16" | "i32" | "u8" | "u16" | "u32" | "u64" | "f32" => {
       let variable = Ident::new(variable, Span::call_site());
       q!(#variable)
     }
@@ -101,6 +130,16 @@ fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2
       let variable = Ident::new(variable, Span::call_site());
       q!(#variable != 0)
     }
+    bytes if is_byte_buffer(bytes) => {
+      let variable_name = variable;
+      let variable = Ident::new(variable, Span::call_site());
+      let copy = copy_bytes(variable, variable_name, ty);
+      q! {
+        {
+          #copy
+        }
+      }
+    }
     serialized if !serialized.starts_with("Option<") => {
       let variable_name = variable;
       let variable = Ident::new(variable, Span::call_site());
@@ -112,19 +151,12 @@ fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2
       }
     }
     "Option<String>" => {
-      let variable_name = variable;
+      let parameter = variable.to_mixed_case();
       let variable = Ident::new(variable, Span::call_site());
       q! {
-        match unsafe { #variable.as_ref() } {
-          Some(val) => {
-            match unsafe { CStr::from_ptr(val).to_str() } {
-              Ok(s) => Some(s.to_string()),
-              Err(e) => {
-                panic!("An invalid string {:?} was received for {}. {:?}", val, #variable_name, e);
-              }
-            }
-          },
-          None => None
+        match #variable.is_null() {
+          false => Some(unsafe { ::membrane::read_argument_string(#variable, #parameter) }?),
+          true => None
         }
       }
     }
@@ -137,7 +169,8 @@ fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2
         }
       }
     }
-    "Option<f64>" => {
+    "Option<f64>" | "Option<i8>" | "Option<i16>" | "Option<i32>" | "Option<u8>" | "Option<u16>"
+    | "Option<u32>" | "Option<u64>" | "Option<f32>" => {
       let variable = Ident::new(variable, Span::call_site());
       q! {
         match unsafe { #variable.as_ref() } {
@@ -161,7 +194,11 @@ fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2
       let ty = extract_type_from_option(ty).unwrap();
       let str_ty = q!(#ty).to_string().split_whitespace().collect::<String>();
 
-      let deserialize = deserialize(variable.clone(), variable_name, ty, str_ty.as_str());
+      let deserialize = if is_byte_buffer(&str_ty) {
+        copy_bytes(variable.clone(), variable_name, ty)
+      } else {
+        deserialize(variable.clone(), variable_name, ty, str_ty.as_str())
+      };
 
       q! {
         {
@@ -182,16 +219,40 @@ fn cast_c_type_to_rust(str_ty: &str, variable: &str, ty: &Type) -> TokenStream2
 }
 
 fn deserialize(variable: Ident, variable_name: &str, ty: &Type, str_ty: &str) -> TokenStream2 {
+  let parameter = variable_name.to_mixed_case();
+  q! {
+    // a malformed or oversized frame fails the call instead of reading past the argument
+    let data = unsafe { ::membrane::read_argument_frame(#variable as *const u8, #parameter) }?;
+    ::membrane::deserialize_argument::<#ty>(MEMBRANE_ENCODING, data, #parameter, #str_ty)?
+  }
+}
+
+fn copy_bytes(variable: Ident, variable_name: &str, ty: &Type) -> TokenStream2 {
+  let parameter = variable_name.to_mixed_case();
   q! {
-    let data = unsafe {
-      // read the first 8 bytes to get the length of the full payload (which includes the length byte)
-      let length = ::std::slice::from_raw_parts::<u8>(#variable, 1 as usize)[0];
-      // read the payload from the pointer
-      ::std::slice::from_raw_parts(#variable, length as usize)
-    };
-    // deserialize, skipping the known 8 byte length field
-    ::membrane::bincode::deserialize::<#ty>(&data[8..]).expect(
-      format!("Deserialization error at variable '{}' of type '{}'", #variable_name, #str_ty).as_str()
-    )
+    let data = unsafe { ::membrane::read_argument_frame(#variable as *const u8, #parameter) }?;
+    // a single copy out of the Dart owned memory, `Bytes` then takes ownership of the `Vec`
+    <#ty as ::std::convert::From<::std::vec::Vec<u8>>>::from(data.to_vec())
+  }
+}
+
+fn receive_stream(variable: Ident, variable_name: &str, item: &Type) -> TokenStream2 {
+  let parameter = variable_name.to_mixed_case();
+  let str_ty = q!(#item).to_string().split_whitespace().collect::<String>();
+  q! {
+    // Dart pushes encoded items through the sender which is kept in the task handle
+    let (membrane_stream_sender, #variable) =
+      ::membrane::futures::channel::mpsc::unbounded::<::std::vec::Vec<u8>>();
+    // an item that can't be decoded ends the stream and `membrane_stream_rejected` ends the task
+    let #variable = ::membrane::futures::StreamExt::scan(#variable, (), move |_, data| {
+      let item = match ::membrane::deserialize_argument::<#item>(MEMBRANE_ENCODING, &data, #parameter, #str_ty) {
+        Ok(item) => Some(item),
+        Err(err) => {
+          membrane_stream_rejected(err);
+          None
+        }
+      };
+      ::membrane::futures::future::ready(item)
+    });
   }
 }
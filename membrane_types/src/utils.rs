@@ -1,6 +1,63 @@
 use syn::{GenericArgument, Path, PathArguments, PathSegment};
 
 pub fn extract_type_from_option(ty: &syn::Type) -> Option<&syn::Type> {
+  extract_generic_type(
+    ty,
+    &["Option|", "std|option|Option|", "core|option|Option|"],
+  )
+}
+
+pub fn extract_type_from_vec(ty: &syn::Type) -> Option<&syn::Type> {
+  extract_generic_type(ty, &["Vec|", "std|vec|Vec|", "alloc|vec|Vec|"])
+}
+
+pub fn extract_types_from_map(ty: &syn::Type) -> Option<(&syn::Type, &syn::Type)> {
+  let paths = [
+    "HashMap|",
+    "std|collections|HashMap|",
+    "BTreeMap|",
+    "std|collections|BTreeMap|",
+    "alloc|collections|BTreeMap|",
+  ];
+  let mut types = extract_generic_types(ty, &paths).into_iter();
+  match (types.next(), types.next()) {
+    (Some(key), Some(value)) => Some((key, value)),
+    _ => None,
+  }
+}
+
+pub fn extract_type_from_progress(ty: &syn::Type) -> Option<&syn::Type> {
+  extract_generic_type(ty, &["Progress|", "membrane|Progress|"])
+}
+
+pub fn extract_type_from_stream(ty: &syn::Type) -> Option<&syn::Type> {
+  let bounds = match ty {
+    syn::Type::ImplTrait(impl_trait) => &impl_trait.bounds,
+    _ => return None,
+  };
+
+  bounds.iter().find_map(|bound| match bound {
+    syn::TypeParamBound::Trait(bound) => {
+      let segment = bound.path.segments.last()?;
+      match &segment.arguments {
+        PathArguments::AngleBracketed(params) if segment.ident == "Stream" => {
+          params.args.iter().find_map(|arg| match arg {
+            GenericArgument::Binding(binding) if binding.ident == "Item" => Some(&binding.ty),
+            _ => None,
+          })
+        }
+        _ => None,
+      }
+    }
+    _ => None,
+  })
+}
+
+fn extract_generic_type<'a>(ty: &'a syn::Type, paths: &[&str]) -> Option<&'a syn::Type> {
+  extract_generic_types(ty, paths).into_iter().next()
+}
+
+fn extract_generic_types<'a>(ty: &'a syn::Type, paths: &[&str]) -> Vec<&'a syn::Type> {
   fn extract_type_path(ty: &syn::Type) -> Option<&Path> {
     match *ty {
       syn::Type::Path(ref typepath) if typepath.qself.is_none() => Some(&typepath.path),
@@ -8,34 +65,31 @@ pub fn extract_type_from_option(ty: &syn::Type) -> Option<&syn::Type> {
     }
   }
 
-  fn extract_option_segment(path: &Path) -> Option<&PathSegment> {
-    let idents_of_path = path
-      .segments
+  let extract_segment = |path: &'a Path| -> Option<&'a PathSegment> {
+    let idents_of_path = path.segments.iter().fold(String::new(), |mut acc, v| {
+      acc.push_str(&v.ident.to_string());
+      acc.push('|');
+      acc
+    });
+
+    paths
       .iter()
-      .into_iter()
-      .fold(String::new(), |mut acc, v| {
-        acc.push_str(&v.ident.to_string());
-        acc.push('|');
-        acc
-      });
-
-    vec!["Option|", "std|option|Option|", "core|option|Option|"]
-      .into_iter()
-      .find(|s| idents_of_path == *s)
+      .find(|s| idents_of_path == **s)
       .and_then(|_| path.segments.last())
-  }
+  };
 
   extract_type_path(ty)
-    .and_then(extract_option_segment)
-    .and_then(|path_seg| {
-      let type_params = &path_seg.arguments;
-      match *type_params {
-        PathArguments::AngleBracketed(ref params) => params.args.first(),
-        _ => None,
-      }
-    })
-    .and_then(|generic_arg| match *generic_arg {
-      GenericArgument::Type(ref ty) => Some(ty),
-      _ => None,
+    .and_then(extract_segment)
+    .map(|path_seg| match path_seg.arguments {
+      PathArguments::AngleBracketed(ref params) => params
+        .args
+        .iter()
+        .filter_map(|generic_arg| match *generic_arg {
+          GenericArgument::Type(ref ty) => Some(ty),
+          _ => None,
+        })
+        .collect(),
+      _ => vec![],
     })
+    .unwrap_or_default()
 }